@@ -0,0 +1,165 @@
+use std::sync::{Condvar, Mutex};
+
+// A channel for one message at a time, where the sender finds out whether
+// the receiver handled it: after send, wait_ack blocks until the receiver
+// acks the message, or nacks it, which gives it back to the sender to try
+// again. Once wait_ack returns, the next message can be sent.
+//
+// Cons: One message in flight at a time, a sender can't send the next one
+// before the last one was acked, so this is for messages that have to get
+// through, not for throughput.
+pub struct Channel<T> {
+    state: Mutex<State<T>>,
+    // Both sides wait on this, they never wait at the same step, so a
+    // notify_all always reaches whoever the change is for
+    changed: Condvar,
+}
+
+enum State<T> {
+    // Nothing sent, or the last message's outcome was picked up
+    Empty,
+    Sent(T),
+    // Taken by receive, but neither acked nor nacked yet
+    Received,
+    Acked,
+    Failed(T),
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        Self { state: Mutex::new(State::Empty), changed: Condvar::new() }
+    }
+
+    // Panics if the last message's outcome wasn't picked up by wait_ack yet
+    pub fn send(&self, message: T) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Empty) {
+            panic!("The last message wasn't acked yet!");
+        }
+        *state = State::Sent(message);
+        drop(state);
+        self.changed.notify_all();
+    }
+
+    // Blocks until the receiver acks or nacks the message. A nacked message
+    // comes back in Err, so it can be sent again. Either way the channel is
+    // empty again afterwards.
+    // Panics if nothing was sent
+    pub fn wait_ack(&self) -> Result<(), T> {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match std::mem::replace(&mut *state, State::Empty) {
+                State::Acked => return Ok(()),
+                State::Failed(message) => return Err(message),
+                State::Empty => panic!("Nothing was sent!"),
+                waiting => *state = waiting,
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    // Blocks until there's a message. It has to be acked or nacked after,
+    // or the sender's wait_ack waits forever.
+    pub fn receive(&self) -> T {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            match std::mem::replace(&mut *state, State::Received) {
+                State::Sent(message) => return message,
+                other => *state = other,
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+
+    // The received message was handled
+    // Panics if there's no received message to ack
+    pub fn ack(&self) {
+        self.finish(State::Acked);
+    }
+
+    // The received message couldn't be handled, gives it back to the sender
+    // Panics if there's no received message to nack
+    pub fn nack(&self, message: T) {
+        self.finish(State::Failed(message));
+    }
+
+    // Receives, and lets f decide right away whether the message was handled:
+    // true acks it, false nacks it. f only borrows the message, so T doesn't
+    // have to be Clone: an acked message is given to the caller, a nacked one
+    // goes back to the sender instead, and the caller gets None.
+    pub fn receive_and_decide(&self, f: impl FnOnce(&T) -> bool) -> Option<T> {
+        let message = self.receive();
+        if f(&message) {
+            self.ack();
+            Some(message)
+        } else {
+            self.nack(message);
+            None
+        }
+    }
+
+    fn finish(&self, outcome: State<T>) {
+        let mut state = self.state.lock().unwrap();
+        if !matches!(*state, State::Received) {
+            panic!("No received message to ack!");
+        }
+        *state = outcome;
+        drop(state);
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    // Not Clone, which receive_and_decide used to require
+    #[derive(Debug, PartialEq)]
+    struct Job(u32);
+
+    #[test]
+    fn rejected_message_goes_back_to_the_sender() {
+        let c = Channel::<Job>::new();
+        thread::scope(|s| {
+            s.spawn(|| assert_eq!(c.receive_and_decide(|job| job.0 > 10), None));
+            c.send(Job(1));
+            assert_eq!(c.wait_ack(), Err(Job(1)));
+        });
+    }
+
+    #[test]
+    fn accepted_message_is_acked_and_kept() {
+        let c = Channel::<Job>::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive_and_decide(|job| job.0 > 10));
+            c.send(Job(11));
+            assert_eq!(c.wait_ack(), Ok(()));
+            assert_eq!(h.join().unwrap(), Some(Job(11)));
+        });
+    }
+
+    #[test]
+    fn nacked_message_can_be_sent_again() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                let first = c.receive();
+                c.nack(first);
+                let second = c.receive();
+                assert_eq!(second, Job(2));
+                c.ack();
+            });
+            c.send(Job(2));
+            let back = c.wait_ack().unwrap_err();
+            c.send(back);
+            assert_eq!(c.wait_ack(), Ok(()));
+        });
+    }
+}
@@ -0,0 +1,82 @@
+use std::any::{Any, TypeId};
+use crate::channels::mutex_based;
+
+// A channel for messages of any type, so one channel can carry all the
+// different kinds of messages of a message bus. Every message is boxed as a
+// Box<dyn Any + Send>, and the receiver says which type it expects, it's
+// checked when it's received.
+//
+// Cons: Every message is a heap allocation, and a type mismatch is only
+// found out at runtime, where a channel per type (or an enum) catches it at
+// compile time
+pub struct AnyChannel {
+    channel: mutex_based::Channel<Box<dyn Any + Send>>,
+}
+
+// The message received wasn't of the type asked for. It's still in here, so
+// it isn't lost, and can be received as something else, or sent on again.
+#[derive(Debug)]
+pub struct WrongType(pub Box<dyn Any + Send>);
+
+impl WrongType {
+    // What the message actually is. Not the Box's TypeId, the Box derefs
+    // to the message
+    pub fn type_id(&self) -> TypeId {
+        (*self.0).type_id()
+    }
+}
+
+impl Default for AnyChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AnyChannel {
+    pub fn new() -> Self {
+        Self { channel: mutex_based::Channel::new() }
+    }
+
+    pub fn send_typed<T: Any + Send>(&self, message: T) {
+        self.channel.send(Box::new(message));
+    }
+
+    // Blocks until there's a message, then takes it whatever its type is
+    pub fn receive(&self) -> Box<dyn Any + Send> {
+        self.channel.receive()
+    }
+
+    // Blocks until there's a message, and gives it back in WrongType if it
+    // isn't a T. Either way the message is no longer in the channel.
+    pub fn receive_typed<T: Any>(&self) -> Result<T, WrongType> {
+        match self.receive().downcast::<T>() {
+            Ok(message) => Ok(*message),
+            Err(message) => Err(WrongType(message)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn each_message_comes_out_as_its_own_type() {
+        let c = AnyChannel::new();
+        c.send_typed(1i32);
+        c.send_typed(String::from("two"));
+        assert_eq!(c.receive_typed::<i32>().unwrap(), 1);
+        assert_eq!(c.receive_typed::<String>().unwrap(), "two");
+    }
+
+    #[test]
+    fn wrong_type_gives_the_message_back() {
+        let c = AnyChannel::new();
+        c.send_typed(String::from("not a number"));
+        let wrong = c.receive_typed::<i32>().unwrap_err();
+        assert_eq!(wrong.type_id(), TypeId::of::<String>());
+        // Not lost, it can go round again as the right type
+        c.channel.send(wrong.0);
+        assert_eq!(c.receive_typed::<String>().unwrap(), "not a number");
+    }
+}
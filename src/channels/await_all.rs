@@ -0,0 +1,154 @@
+use std::array;
+use std::cell::UnsafeCell;
+use std::future::Future;
+use std::mem::MaybeUninit;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::task::{Context, Poll, Waker};
+
+// N oneshots in one: N senders that each send one value, and a future that
+// completes once all N have, giving back the values in sender order.
+// A fixed size join for async code.
+//
+// Each Sender is consumed by send, like in compile_time_oneshot, so every
+// slot is written at most once.
+// If a Sender is dropped without sending, the future never completes.
+pub struct AwaitAll<T, const N: usize> {
+    shared: Arc<Shared<T, N>>,
+}
+
+pub struct Sender<T, const N: usize> {
+    shared: Arc<Shared<T, N>>,
+    index: usize,
+}
+
+// The future returned by AwaitAll::await_all
+pub struct AllReady<T, const N: usize> {
+    shared: Arc<Shared<T, N>>,
+    done: bool,
+}
+
+struct Shared<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // Which slots hold a value, so Drop knows what to drop
+    written: [AtomicBool; N],
+    // How many slots have been written
+    ready: AtomicUsize,
+    // The waker of the task awaiting AwaitAll, if it's waiting
+    waker: Mutex<Option<Waker>>,
+}
+
+unsafe impl<T, const N: usize> Sync for Shared<T, N> where T: Send {}
+
+impl<T, const N: usize> Drop for Shared<T, N> {
+    fn drop(&mut self) {
+        for (slot, written) in self.slots.iter_mut().zip(&mut self.written) {
+            if *written.get_mut() {
+                unsafe { slot.get_mut().assume_init_drop() }
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> Sender<T, N> {
+    pub fn send(self, value: T) {
+        let shared = &*self.shared;
+        // Safety: Only this sender has this index, and send consumes it
+        unsafe { (*shared.slots[self.index].get()).write(value); }
+        shared.written[self.index].store(true, Relaxed);
+        // Release, so the value is visible to whoever sees the count reach N.
+        // fetch_add is a read-modify-write, so the releases of all the senders
+        // before us are part of the chain too.
+        if shared.ready.fetch_add(1, Release) + 1 == N {
+            if let Some(waker) = shared.waker.lock().unwrap().take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+impl<T, const N: usize> AwaitAll<T, N> {
+    pub fn new() -> (Self, [Sender<T, N>; N]) {
+        let shared = Arc::new(Shared {
+            slots: array::from_fn(|_| UnsafeCell::new(MaybeUninit::uninit())),
+            written: array::from_fn(|_| AtomicBool::new(false)),
+            ready: AtomicUsize::new(0),
+            waker: Mutex::new(None),
+        });
+        let senders = array::from_fn(|index| Sender { shared: shared.clone(), index });
+        (Self { shared }, senders)
+    }
+
+    pub fn await_all(self) -> AllReady<T, N> {
+        AllReady { shared: self.shared, done: false }
+    }
+}
+
+impl<T, const N: usize> AllReady<T, N> {
+    // Only called once ready is N (seen with Acquire)
+    fn take_all(&mut self) -> [T; N] {
+        // A future shouldn't be polled again after returning Ready, but that's
+        // not something unsafe code can rely on
+        assert!(!self.done, "AllReady polled after completion!");
+        self.done = true;
+        let shared = &*self.shared;
+        array::from_fn(|i| {
+            shared.written[i].store(false, Relaxed);
+            // Safety: Every slot was written, and done makes sure we only read
+            // them once
+            unsafe { (*shared.slots[i].get()).assume_init_read() }
+        })
+    }
+}
+
+impl<T, const N: usize> Future for AllReady<T, N> {
+    type Output = [T; N];
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<[T; N]> {
+        if self.shared.ready.load(Acquire) == N {
+            return Poll::Ready(self.take_all());
+        }
+        *self.shared.waker.lock().unwrap() = Some(cx.waker().clone());
+        // The last sender might have finished between our first check and us
+        // storing the waker, in which case nobody is going to wake us, so
+        // check again
+        if self.shared.ready.load(Acquire) == N {
+            return Poll::Ready(self.take_all());
+        }
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::test_util::block_on;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn completes_once_every_slot_is_filled() {
+        let (all, senders) = AwaitAll::<String, 3>::new();
+        thread::scope(|s| {
+            for (i, sender) in senders.into_iter().enumerate() {
+                s.spawn(move || {
+                    // Last slot first, so the values arrive out of order
+                    thread::sleep(Duration::from_millis(10 * (3 - i as u64)));
+                    sender.send(i.to_string());
+                });
+            }
+            assert_eq!(block_on(all.await_all()), ["0", "1", "2"]);
+        });
+    }
+
+    #[test]
+    fn drops_values_that_were_never_awaited() {
+        let value = Arc::new(());
+        let (all, [a, b]) = AwaitAll::<Arc<()>, 2>::new();
+        a.send(value.clone());
+        drop((all, b));
+        assert_eq!(Arc::strong_count(&value), 1);
+    }
+}
@@ -0,0 +1,98 @@
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use crate::channels::mutex_based;
+
+// A channel where the receiver can tell senders to hold off: after pause,
+// every send blocks until resume, no matter how much room the queue has.
+// That leaves flow control up to the consumer, which can throttle producers
+// based on its own load instead of the queue length.
+// Messages already queued can still be received while paused.
+pub struct Channel<T> {
+    queue: mutex_based::Channel<T>,
+    // Doesn't guard any data, the gate mutex is what senders wait with, so
+    // Relaxed is enough
+    paused: AtomicBool,
+    gate: Mutex<()>,
+    resumed: Condvar,
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Channel<T> {
+    pub fn new() -> Self {
+        Self {
+            queue: mutex_based::Channel::new(),
+            paused: AtomicBool::new(false),
+            gate: Mutex::new(()),
+            resumed: Condvar::new(),
+        }
+    }
+
+    // Blocks while the receiver has paused senders
+    pub fn send(&self, message: T) {
+        // Not paused is the common case, no need for the lock then
+        if self.paused.load(Relaxed) {
+            let mut gate = self.gate.lock().unwrap();
+            while self.paused.load(Relaxed) {
+                gate = self.resumed.wait(gate).unwrap();
+            }
+        }
+        self.queue.send(message);
+    }
+
+    pub fn receive(&self) -> T {
+        self.queue.receive()
+    }
+
+    // Makes sends block until resume. A send that's already past the check
+    // still goes through.
+    pub fn pause(&self) {
+        self.paused.store(true, Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Relaxed);
+        // Taking the lock makes sure a sender that just saw paused is already
+        // waiting, so it doesn't miss this
+        drop(self.gate.lock().unwrap());
+        self.resumed.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn paused_sender_waits_for_resume() {
+        let c = Channel::new();
+        c.send(1);
+        c.pause();
+        assert!(c.is_paused());
+        let sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                c.send(2);
+                sent.store(true, Relaxed);
+            });
+            thread::sleep(Duration::from_millis(20));
+            assert!(!sent.load(Relaxed));
+            // What's queued can still be received while paused
+            assert_eq!(c.receive(), 1);
+            c.resume();
+        });
+        assert!(sent.load(Relaxed));
+        assert_eq!(c.receive(), 2);
+    }
+}
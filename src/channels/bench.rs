@@ -0,0 +1,209 @@
+use std::sync::atomic::{AtomicBool, AtomicU32};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::channels::wake::{self, YieldStrategy};
+use crate::channels::{
+    borrowing_oneshot, compile_time_oneshot, generational_oneshot, inline_oneshot, safer_oneshot,
+    unsafe_oneshot,
+};
+
+// A simple harness comparing the oneshot channels, to help pick one.
+// Every channel gets the same workload: one message per round, each on a new
+// channel, from one producer thread to this thread, which waits for it with
+// YieldStrategy. The producer only sends the next message once this thread
+// has received the one before, so every round is one send-to-receive
+// handoff, and the messages don't pile up.
+// Everything that isn't the channel is kept out of the timed part: all the
+// channels are made up front, and the producer is spawned before the clock
+// starts, and lives for all the rounds.
+//
+// Every round checks the value that came out is the one that went in, so a
+// run doubles as a smoke test of every channel.
+
+#[derive(Debug)]
+pub struct BenchResult {
+    pub name: &'static str,
+    pub rounds: u32,
+    pub total: Duration,
+}
+
+impl BenchResult {
+    // Average time from sending a message to having received it
+    pub fn latency(&self) -> Duration {
+        self.total / self.rounds.max(1)
+    }
+
+    // Messages per second, 0 if nothing was measured
+    pub fn throughput(&self) -> f64 {
+        if self.total.is_zero() {
+            return 0.0;
+        }
+        self.rounds as f64 / self.total.as_secs_f64()
+    }
+}
+
+// Runs every channel for the given number of rounds
+pub fn run_all(rounds: u32) -> Vec<BenchResult> {
+    vec![
+        bench_unsafe(rounds),
+        bench_safer(rounds),
+        bench_compile_time(rounds),
+        bench_borrowing(rounds),
+        bench_inline(rounds),
+        bench_generational(rounds),
+    ]
+}
+
+// One line per channel, for printing
+pub fn report(results: &[BenchResult]) -> String {
+    results
+        .iter()
+        .map(|r| format!("{:<22} {:>10.2?}/message {:>12.0} messages/s\n", r.name, r.latency(), r.throughput()))
+        .collect()
+}
+
+// Sends round i on senders[i] from the producer thread, and receives it from
+// receivers[i] on this one, timing only the rounds themselves
+fn measure<S: Send, R>(
+    name: &'static str,
+    senders: Vec<S>,
+    receivers: Vec<R>,
+    send: impl Fn(S, u32) + Send,
+    receive: impl Fn(R) -> u32,
+) -> BenchResult {
+    let rounds = receivers.len() as u32;
+    // How many messages this thread has received, the producer waits for it
+    // to catch up before every send
+    let received = &AtomicU32::new(0);
+    let go = &AtomicBool::new(false);
+    let total = thread::scope(|s| {
+        s.spawn(move || {
+            wake::wait_until(&YieldStrategy, || go.load(Relaxed));
+            for (i, sender) in (0..).zip(senders) {
+                wake::wait_until(&YieldStrategy, || received.load(Acquire) == i);
+                send(sender, i);
+            }
+        });
+        let start = Instant::now();
+        go.store(true, Relaxed);
+        for (i, receiver) in (0..).zip(receivers) {
+            assert_eq!(receive(receiver), i, "{name} received the wrong message!");
+            received.store(i + 1, Release);
+        }
+        start.elapsed()
+    });
+    BenchResult { name, rounds, total }
+}
+
+fn bench_unsafe(rounds: u32) -> BenchResult {
+    let channels: Vec<_> = (0..rounds).map(|_| unsafe_oneshot::Channel::new()).collect();
+    measure(
+        "unsafe_oneshot",
+        channels.iter().collect(),
+        channels.iter().collect(),
+        // Safety: The only send on this channel
+        |c, message| unsafe { c.send(message) },
+        |c| {
+            wake::wait_until(&YieldStrategy, || c.is_ready());
+            // Safety: The only receive, after is_ready
+            unsafe { c.receive() }
+        },
+    )
+}
+
+fn bench_safer(rounds: u32) -> BenchResult {
+    let channels: Vec<_> = (0..rounds).map(|_| safer_oneshot::Channel::new()).collect();
+    measure(
+        "safer_oneshot",
+        channels.iter().collect(),
+        channels.iter().collect(),
+        |c, message| c.send(message),
+        |c| c.receive_with(YieldStrategy).unwrap(),
+    )
+}
+
+fn bench_compile_time(rounds: u32) -> BenchResult {
+    let (senders, receivers) = (0..rounds).map(|_| compile_time_oneshot::channel()).unzip();
+    measure(
+        "compile_time_oneshot",
+        senders,
+        receivers,
+        |sender, message| sender.send(message),
+        |receiver| receiver.receive_with(YieldStrategy).unwrap(),
+    )
+}
+
+fn bench_borrowing(rounds: u32) -> BenchResult {
+    let mut channels: Vec<_> = (0..rounds).map(|_| borrowing_oneshot::Channel::new()).collect();
+    let (senders, receivers) = channels.iter_mut().map(|c| c.split()).unzip();
+    measure(
+        "borrowing_oneshot",
+        senders,
+        receivers,
+        |sender, message| sender.send(message),
+        |receiver: borrowing_oneshot::Receiver<'_, u32>| {
+            wake::wait_until(&YieldStrategy, || receiver.is_ready());
+            receiver.receive()
+        },
+    )
+}
+
+fn bench_inline(rounds: u32) -> BenchResult {
+    let channels: Vec<_> = (0..rounds).map(|_| inline_oneshot::Channel::new()).collect();
+    measure(
+        "inline_oneshot",
+        channels.iter().collect(),
+        channels.iter().collect(),
+        |c, message| c.send(message),
+        |c| {
+            wake::wait_until(&YieldStrategy, || c.is_ready());
+            c.receive()
+        },
+    )
+}
+
+fn bench_generational(rounds: u32) -> BenchResult {
+    let channels: Vec<_> = (0..rounds).map(|_| generational_oneshot::Channel::new()).collect();
+    measure(
+        "generational_oneshot",
+        channels.iter().map(|c| c.sender()).collect(),
+        channels.iter().collect(),
+        |sender, message| sender.send(message).unwrap(),
+        |c| {
+            wake::wait_until(&YieldStrategy, || c.is_ready());
+            c.try_receive().unwrap()
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_channel_delivers_the_right_value() {
+        let results = run_all(20);
+        let names: Vec<_> = results.iter().map(|r| r.name).collect();
+        assert_eq!(
+            names,
+            [
+                "unsafe_oneshot",
+                "safer_oneshot",
+                "compile_time_oneshot",
+                "borrowing_oneshot",
+                "inline_oneshot",
+                "generational_oneshot",
+            ]
+        );
+        assert!(results.iter().all(|r| r.rounds == 20));
+        assert_eq!(report(&results).lines().count(), results.len());
+    }
+
+    #[test]
+    fn latency_of_no_rounds_doesnt_divide_by_zero() {
+        let r = BenchResult { name: "none", rounds: 0, total: Duration::ZERO };
+        assert_eq!(r.latency(), Duration::ZERO);
+        assert_eq!(r.throughput(), 0.0);
+    }
+}
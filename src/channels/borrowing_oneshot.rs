@@ -1,16 +1,21 @@
 use std::cell::UnsafeCell;
 use std::mem::MaybeUninit;
 use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use crate::channels::leak_check::Tracker;
 
 /// For this implementation we will have the user be responsible for the
 /// shared channel object, they will create the Channel in a local variable,
 /// and Sender/Receiver will borrow it. This avoids the overhead of allocating
 /// memory.
 ///
-/// Pros: No memory allocation with
+/// Pros: No memory allocation with an Arc, like compile_time_oneshot needs
+/// Cons: The borrows tie the Sender and Receiver to the Channel's lifetime,
+/// so they can only be sent to scoped threads
 pub struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
     ready: AtomicBool,
+    tracker: Tracker,
 }
 
 unsafe impl<T> Sync for Channel<T> where T: Send {}
@@ -23,3 +28,140 @@ pub struct Receiver<'a, T> {
     channel: &'a Channel<T>,
 }
 
+// Same as compile_time_oneshot, a message that was sent but never received
+// is dropped along with the Channel
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() {
+            unsafe { self.message.get_mut().assume_init_drop() }
+            self.tracker.message_gone();
+        }
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            ready: AtomicBool::new(false),
+            tracker: Tracker::new(),
+        }
+    }
+
+    // Borrowing self mutably means there can only be one Sender and Receiver
+    // pair at a time, the borrow checker won't let us split again until both
+    // are gone.
+    // The channel is reset first, so it can be reused once the previous pair
+    // is done with it.
+    pub fn split<'a>(&'a mut self) -> (Sender<'a, T>, Receiver<'a, T>) {
+        self.reset();
+        (Sender { channel: self }, Receiver { channel: self })
+    }
+
+    // Empties the channel, so it can carry another message. If a message
+    // was sent but never received, overwriting self drops it.
+    // Taking self mutably means no Sender or Receiver can still be borrowing
+    // it, and nothing needs to be synchronized here: whatever thread used
+    // them is done with the channel by the time we get it back (a scoped
+    // thread is joined first, which makes its send visible to us), so just
+    // overwriting self is enough.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl<T> Sender<'_, T> {
+    // Consumes the Sender, like compile_time_oneshot, so this can only be
+    // called once per split
+    pub fn send(self, message: T) {
+        unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.tracker.message_sent();
+        self.channel.ready.store(true, Release);
+    }
+}
+
+impl<T> Receiver<'_, T> {
+    pub fn is_ready(&self) -> bool {
+        self.channel.ready.load(Relaxed)
+    }
+
+    // Panics if no message is available yet, use is_ready to check first
+    pub fn receive(self) -> T {
+        if !self.channel.ready.swap(false, Acquire) {
+            panic!("No message available!");
+        }
+        self.channel.tracker.message_gone();
+        // Safety: We've just checked (and reset) the ready flag
+        unsafe { (*self.channel.message.get()).assume_init_read() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn send_from_a_scoped_thread() {
+        // On the stack, nothing here allocates
+        let mut channel = Channel::new();
+        thread::scope(|s| {
+            let (sender, receiver) = channel.split();
+            s.spawn(move || sender.send(String::from("hello")));
+            while !receiver.is_ready() {
+                thread::yield_now();
+            }
+            assert_eq!(receiver.receive(), "hello");
+        });
+    }
+
+    #[test]
+    fn split_again_after_reuse() {
+        let unreceived = std::sync::Arc::new(());
+        let mut channel = Channel::new();
+        let (sender, _) = channel.split();
+        sender.send(unreceived.clone());
+        // Splitting resets, so the unreceived message is dropped
+        let (sender, receiver) = channel.split();
+        assert_eq!(std::sync::Arc::strong_count(&unreceived), 1);
+        assert!(!receiver.is_ready());
+        sender.send(unreceived.clone());
+        assert!(std::sync::Arc::ptr_eq(&receiver.receive(), &unreceived));
+    }
+
+    #[test]
+    #[should_panic(expected = "No message available!")]
+    fn receive_before_send_panics() {
+        let mut channel = Channel::<i32>::new();
+        let (_sender, receiver) = channel.split();
+        receiver.receive();
+    }
+
+    #[test]
+    fn reset_makes_room_for_another_message() {
+        let mut channel = Channel::new();
+        let (sender, receiver) = channel.split();
+        sender.send("first");
+        assert_eq!(receiver.receive(), "first");
+        channel.reset();
+        assert!(!channel.ready.load(Relaxed));
+        let (sender, receiver) = channel.split();
+        sender.send("second");
+        assert_eq!(receiver.receive(), "second");
+    }
+
+    #[test]
+    fn reset_drops_an_unreceived_message() {
+        let message = std::sync::Arc::new(());
+        let mut channel = Channel::new();
+        channel.split().0.send(message.clone());
+        channel.reset();
+        assert_eq!(std::sync::Arc::strong_count(&message), 1);
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering::Relaxed;
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use crate::channels::mutex_based::{self, RecvTimeoutError};
+
+// A bridge from blocking producers to async consumers: a thread drains a
+// mutex_based receiver, and the messages come out of an AsyncReceiver, which
+// async code can await without blocking its executor.
+
+// How long the forwarding thread waits for a message before checking whether
+// the AsyncReceiver is still there
+const CHECK_INTERVAL: Duration = Duration::from_millis(10);
+
+pub struct AsyncReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// The future returned by AsyncReceiver::recv
+pub struct Recv<'a, T> {
+    receiver: &'a AsyncReceiver<T>,
+}
+
+struct Shared<T> {
+    // Everything the future looks at is behind the one lock, so the
+    // forwarder can't push a message (or close) between the future finding
+    // nothing and the future storing its waker
+    state: Mutex<State<T>>,
+    // Set when the AsyncReceiver is dropped, so the forwarder can stop.
+    // Nothing is synchronized through it, so Relaxed is enough
+    receiver_gone: AtomicBool,
+}
+
+struct State<T> {
+    queue: VecDeque<T>,
+    // Set once every sender is gone and the forwarder is done
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Spawns a thread that forwards every message from src into the returned
+/// AsyncReceiver.
+///
+/// Once every Sender is dropped, the AsyncReceiver gets the messages that
+/// were still queued, and then recv gives None. If the AsyncReceiver is
+/// dropped, the thread stops (within CHECK_INTERVAL), dropping src. The
+/// senders can't tell, anything they send after that stays queued until the
+/// last of them is dropped.
+pub fn spawn_forwarder<T>(src: mutex_based::Receiver<T>) -> (AsyncReceiver<T>, JoinHandle<()>)
+where
+    T: Send + 'static,
+{
+    let shared = Arc::new(Shared {
+        state: Mutex::new(State { queue: VecDeque::new(), closed: false, waker: None }),
+        receiver_gone: AtomicBool::new(false),
+    });
+    let forwarder = shared.clone();
+    let handle = thread::spawn(move || {
+        while !forwarder.receiver_gone.load(Relaxed) {
+            match src.recv_timeout(CHECK_INTERVAL) {
+                Ok(message) => forwarder.update(|state| state.queue.push_back(message)),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        forwarder.update(|state| state.closed = true);
+    });
+    (AsyncReceiver { shared }, handle)
+}
+
+impl<T> Shared<T> {
+    // Changes the state, then wakes the waiting task, if there is one
+    fn update(&self, f: impl FnOnce(&mut State<T>)) {
+        let waker = {
+            let mut state = self.state.lock().unwrap();
+            f(&mut state);
+            state.waker.take()
+        };
+        // Woken after unlocking, the task might poll right away
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
+
+impl<T> AsyncReceiver<T> {
+    // Resolves to the next message, or None once every Sender is gone and everything has been received
+    pub fn recv(&mut self) -> Recv<'_, T> {
+        Recv { receiver: self }
+    }
+}
+
+impl<T> Drop for AsyncReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_gone.store(true, Relaxed);
+    }
+}
+
+impl<T> Future for Recv<'_, T> {
+    type Output = Option<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.receiver.shared.state.lock().unwrap();
+        if let Some(message) = state.queue.pop_front() {
+            return Poll::Ready(Some(message));
+        }
+        if state.closed {
+            return Poll::Ready(None);
+        }
+        state.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::test_util::block_on;
+
+    #[test]
+    fn forwards_until_the_senders_are_gone() {
+        let (tx, rx) = mutex_based::channel();
+        let (mut async_rx, handle) = spawn_forwarder(rx);
+        let producer = thread::spawn(move || {
+            for i in 0..3 {
+                tx.send(i);
+                thread::sleep(Duration::from_millis(5));
+            }
+        });
+        let received: Vec<_> = block_on(async {
+            let mut received = Vec::new();
+            while let Some(message) = async_rx.recv().await {
+                received.push(message);
+            }
+            received
+        });
+        assert_eq!(received, [0, 1, 2]);
+        producer.join().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn dropping_the_async_side_stops_the_forwarder() {
+        let (tx, rx) = mutex_based::channel::<i32>();
+        let (async_rx, handle) = spawn_forwarder(rx);
+        drop(async_rx);
+        // Even with a Sender still around
+        handle.join().unwrap();
+        drop(tx);
+    }
+}
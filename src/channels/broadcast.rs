@@ -0,0 +1,139 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+// Every message sent is received by every receiver.
+// A message is only stored once, in an Arc, and every receiver's queue holds
+// a clone of the Arc, so sending to many receivers doesn't copy the message.
+// Cloning a Receiver subscribes a new receiver, which gets every message sent
+// from then on.
+//
+// Cons: Like mutex_based, every operation locks the whole channel, and a
+// receiver that never receives makes its queue grow without bounds
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared {
+        queues: Mutex::new(HashMap::from([(0, VecDeque::new())])),
+        next_id: AtomicUsize::new(1),
+        item_ready: Condvar::new(),
+    });
+    (
+        Sender { shared: shared.clone() },
+        Receiver { shared, id: 0, current: None },
+    )
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    id: usize,
+    // The last message received by receive_cow, kept here so it can hand out
+    // a reference to it
+    current: Option<Arc<T>>,
+}
+
+struct Shared<T> {
+    // One queue per receiver, by id
+    queues: Mutex<HashMap<usize, VecDeque<Arc<T>>>>,
+    next_id: AtomicUsize,
+    item_ready: Condvar,
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, message: T) {
+        let message = Arc::new(message);
+        for queue in self.shared.queues.lock().unwrap().values_mut() {
+            queue.push_back(message.clone());
+        }
+        // Every receiver might be waiting for this
+        self.shared.item_ready.notify_all();
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until there's a message for this receiver
+    pub fn receive(&self) -> Arc<T> {
+        let mut queues = self.shared.queues.lock().unwrap();
+        loop {
+            if let Some(message) = queues.get_mut(&self.id).unwrap().pop_front() {
+                return message;
+            }
+            queues = self.shared.item_ready.wait(queues).unwrap();
+        }
+    }
+
+    // Like receive, but gives the message itself rather than an Arc.
+    // If no other receiver holds on to the message any more (there's only one
+    // receiver, or all the others have dropped it), it's borrowed from this
+    // receiver, without copying it. Otherwise, it has to be cloned, as the other
+    // receivers still share it.
+    // The borrow is valid until the next receive_cow.
+    pub fn receive_cow(&mut self) -> Cow<'_, T>
+    where
+        T: Clone,
+    {
+        // Drop our reference to the previous message first, it might be what's
+        // keeping other receivers from getting a borrow
+        self.current = None;
+        let message = self.current.insert(self.receive());
+        if Arc::strong_count(message) == 1 {
+            Cow::Borrowed(&**message)
+        } else {
+            Cow::Owned((**message).clone())
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        let id = self.shared.next_id.fetch_add(1, Relaxed);
+        self.shared.queues.lock().unwrap().insert(id, VecDeque::new());
+        Self { shared: self.shared.clone(), id, current: None }
+    }
+}
+
+// Stop queueing messages for a receiver that's gone
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.shared.queues.lock().unwrap().remove(&self.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_receiver_gets_every_message() {
+        let (tx, rx) = channel();
+        let rx2 = rx.clone();
+        tx.send(1);
+        tx.send(2);
+        assert_eq!((*rx.receive(), *rx.receive()), (1, 2));
+        assert_eq!((*rx2.receive(), *rx2.receive()), (1, 2));
+    }
+
+    #[test]
+    fn single_reader_borrows() {
+        let (tx, mut rx) = channel();
+        tx.send(String::from("a"));
+        tx.send(String::from("b"));
+        assert!(matches!(rx.receive_cow(), Cow::Borrowed(s) if s == "a"));
+        assert!(matches!(rx.receive_cow(), Cow::Borrowed(s) if s == "b"));
+    }
+
+    #[test]
+    fn shared_messages_are_cloned() {
+        let (tx, mut rx) = channel();
+        let mut rx2 = rx.clone();
+        tx.send(String::from("a"));
+        // rx2 still holds on to it in its queue
+        assert!(matches!(rx.receive_cow(), Cow::Owned(s) if s == "a"));
+        // And rx holds on to it until its next receive_cow
+        assert!(matches!(rx2.receive_cow(), Cow::Owned(s) if s == "a"));
+    }
+}
@@ -0,0 +1,78 @@
+use std::thread;
+use crate::channels::mutex_based::Channel;
+use crate::channels::wake;
+
+// Blocks until n messages have been received in total from the channels, no
+// matter how many of them came from which channel, and gives them back.
+// Messages from the same channel stay in order.
+//
+// A thread can only wait on one condvar at a time, so instead we get unparked
+// by every send to any of the channels, and go through all of them again.
+// Each check does a try_receive on every channel, so this is meant for a
+// handful of channels, not hundreds.
+pub fn wait_for_total<T>(channels: &[&Channel<T>], n: usize) -> Vec<T> {
+    let mut collected = Vec::with_capacity(n);
+    let me = thread::current();
+    for channel in channels {
+        channel.add_observer(me.clone());
+    }
+    // Registered before the first check, so no send can slip in between a
+    // check and parking without unparking us
+    loop {
+        for channel in channels {
+            while collected.len() < n {
+                match channel.try_receive() {
+                    Some(message) => collected.push(message),
+                    None => break,
+                }
+            }
+        }
+        if collected.len() == n {
+            break;
+        }
+        // Unparked by a send to any of the channels, or spuriously,
+        // either way we just check again
+        wake::park();
+    }
+    for channel in channels {
+        channel.remove_observer(&me);
+    }
+    collected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn collects_exactly_n_from_wherever_they_come() {
+        let (a, b, c) = (Channel::new(), Channel::new(), Channel::new());
+        thread::scope(|s| {
+            s.spawn(|| {
+                for (channel, message) in [(&b, 1), (&a, 2), (&b, 3), (&c, 4), (&a, 5)] {
+                    thread::sleep(Duration::from_millis(5));
+                    channel.send(message);
+                }
+            });
+            let mut got = wait_for_total(&[&a, &b, &c], 4);
+            got.sort();
+            assert_eq!(got, [1, 2, 3, 4]);
+        });
+        // The fifth one wasn't needed, and stays queued
+        assert_eq!(a.try_receive(), Some(5));
+        assert_eq!(a.len() + b.len() + c.len(), 0);
+    }
+
+    #[test]
+    fn keeps_each_channels_order() {
+        let (a, b) = (Channel::new(), Channel::new());
+        a.send(1);
+        b.send(10);
+        a.send(2);
+        let got = wait_for_total(&[&a, &b], 3);
+        let from_a: Vec<_> = got.iter().filter(|&&m| m < 10).collect();
+        assert_eq!(from_a, [&1, &2]);
+        assert_eq!(wait_for_total(&[&a, &b], 0), Vec::<i32>::new());
+    }
+}
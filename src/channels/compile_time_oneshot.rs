@@ -1,8 +1,15 @@
 use std::cell::UnsafeCell;
+use std::future::Future;
 use std::mem::MaybeUninit;
-use std::sync::Arc;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, OnceLock};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::task::{Context, Poll, Waker};
+use std::thread::{self, Thread};
+use crate::channels::leak_check::Tracker;
+use crate::channels::poll::{self, Pollable};
+use crate::channels::wake::{self, WakeStrategy};
 
 /// Here we'll be taking an argument by value, which for non-Copy types
 /// will consume the object, preventing reuse of the functions
@@ -19,6 +26,13 @@ pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
     let a = Arc::new(Channel {
         message: UnsafeCell::new(MaybeUninit::uninit()),
         ready: AtomicBool::new(false),
+        consumed: AtomicBool::new(false),
+        sent: AtomicBool::new(false),
+        closed: AtomicBool::new(false),
+        receiver_gone: AtomicBool::new(false),
+        wakers: Mutex::new(Vec::new()),
+        waiter: Mutex::new(None),
+        tracker: Tracker::new(),
     });
     (Sender { channel: a.clone() }, Receiver {channel: a })
 }
@@ -31,6 +45,20 @@ pub struct Receiver<T> {
     channel: Arc<Channel<T>>,
 }
 
+// Lets someone other than the sender and receiver (a supervisor, say) see
+// whether the message has been sent yet. Get one from Sender::monitor before
+// the Sender is consumed by send.
+pub struct MonitorHandle<T> {
+    channel: Arc<Channel<T>>,
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for MonitorHandle<T> {
+    fn clone(&self) -> Self {
+        Self { channel: self.channel.clone() }
+    }
+}
+
 // Inner implementation not relevant to user, so we keep private
 // We don't need the in_use atomic boolean like in the safer_oneshot
 // implementation, as send is now statically guaranteed to only be
@@ -38,6 +66,76 @@ pub struct Receiver<T> {
 struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
     ready: AtomicBool,
+    // Set once the message has been read out of the slot, so a second read
+    // is reported instead of reading a moved-out (or uninitialized) value.
+    // Not needed as long as receive is the only way to get the message, since
+    // it consumes the Receiver, but anything that takes the message without
+    // consuming the Receiver has to go through take, which checks this.
+    consumed: AtomicBool,
+    // Unlike ready, this stays set after the message is received
+    sent: AtomicBool,
+    // Set when the Sender is gone without sending, so nothing will ever
+    // arrive. Nothing is written before it that the receiver reads, so
+    // Relaxed is enough.
+    closed: AtomicBool,
+    // Set when the Receiver is dropped (receive consumes it, so also after
+    // receiving), see Sender::is_receiver_alive
+    receiver_gone: AtomicBool,
+    // Tasks awaiting a SharedReceiver, woken by send
+    wakers: Mutex<Vec<Waker>>,
+    // The thread waiting in receive_with, unparked by send, or by the Sender
+    // being dropped. The lock orders registering with sending: either the
+    // sender finds the thread here, or the receiver sees ready (or closed)
+    // when it checks after registering.
+    waiter: Mutex<Option<Thread>>,
+    tracker: Tracker,
+}
+
+// From try_send, holding the message that wasn't sent
+#[derive(PartialEq, Eq)]
+pub struct SendError<T>(T);
+
+impl<T> SendError<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+// Not derived, so it doesn't need T: Debug, like std's SendError
+impl<T> std::fmt::Debug for SendError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SendError { .. }")
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvError {
+    // No message has been sent yet
+    NotReady,
+    // The message was already taken out of the channel
+    AlreadyConsumed,
+    // The Sender was cancelled, or dropped without sending
+    SenderDropped,
+}
+
+// From try_receive, like RecvError, but NotReady gives the Receiver back to
+// try again with
+pub enum TryRecvError<T> {
+    NotReady(Receiver<T>),
+    // Polling took the message out already
+    AlreadyConsumed,
+    SenderDropped,
+}
+
+// Not derived, so it doesn't need Receiver (or T) to be Debug
+impl<T> std::fmt::Debug for TryRecvError<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotReady(_) => f.write_str("NotReady(..)"),
+            Self::AlreadyConsumed => f.write_str("AlreadyConsumed"),
+            Self::SenderDropped => f.write_str("SenderDropped"),
+        }
+    }
 }
 
 // Now that we've specified Channel is Sync, Sender and Receiver are also Sync.
@@ -57,10 +155,64 @@ impl<T> Drop for Channel<T> {
         // they are mutable
         if *self.ready.get_mut() {
             unsafe { self.message.get_mut().assume_init_drop() }
+            self.tracker.message_gone();
         }
     }
 }
 
+impl<T> Channel<T> {
+    // The only place the message gets read out of the slot.
+    // Only one Receiver exists, so nothing can race us between the checks.
+    fn take(&self) -> Result<T, RecvError> {
+        if self.consumed.load(Relaxed) {
+            return Err(RecvError::AlreadyConsumed);
+        }
+        // swap used so drop knows whether there is an unread message
+        // that needs to be dropped
+        if !self.ready.swap(false, Acquire) {
+            if self.closed.load(Relaxed) {
+                return Err(RecvError::SenderDropped);
+            }
+            return Err(RecvError::NotReady);
+        }
+        self.consumed.store(true, Relaxed);
+        self.tracker.message_gone();
+        // Safety: ready was set by send, and we've just reset it, so this is
+        // the only read of the message
+        Ok(unsafe { (*self.message.get()).assume_init_read() })
+    }
+
+    // Wakes every task awaiting a SharedReceiver, and the thread waiting in
+    // receive_with. Taken out of the lock first, a waker might do anything
+    // when woken
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        for waker in wakers {
+            waker.wake();
+        }
+        if let Some(thread) = self.waiter.lock().unwrap().take() {
+            wake::unpark(&thread);
+        }
+    }
+}
+
+// send sets sent before its self is dropped here, so this only closes the
+// channel if nothing was sent
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if !self.channel.sent.load(Relaxed) {
+            self.channel.closed.store(true, Relaxed);
+            self.channel.wake_all();
+        }
+    }
+}
+
+impl<T> Drop for Receiver<T> {
+    fn drop(&mut self) {
+        self.channel.receiver_gone.store(true, Relaxed);
+    }
+}
+
 impl<T> Sender<T> {
     // Once this is called the Sender object is consumed, and we can no
     // longer call this fn
@@ -68,7 +220,43 @@ impl<T> Sender<T> {
     // once) is now statically guaranteed
     pub fn send(self, message: T) {
         unsafe { (*self.channel.message.get()).write(message) };
+        self.channel.tracker.message_sent();
         self.channel.ready.store(true, Release);
+        self.channel.sent.store(true, Relaxed);
+        self.channel.wake_all();
+    }
+
+    // Like send, but gives the message back if the Receiver is already gone,
+    // so a message no one will read can be recovered with into_inner. Only
+    // advisory, like is_receiver_alive: if the Receiver goes away right after
+    // the check, the message is sent anyway and dropped with the channel.
+    pub fn try_send(self, message: T) -> Result<(), SendError<T>> {
+        if !self.is_receiver_alive() {
+            return Err(SendError(message));
+        }
+        self.send(message);
+        Ok(())
+    }
+
+    // Gives up on sending, the receiver gets RecvError::SenderDropped instead
+    // of waiting forever, and a receiver waiting in receive_with is woken up.
+    // Same as dropping the Sender, this just says so.
+    pub fn cancel(self) {
+        drop(self);
+    }
+
+    // Whether anyone is still going to receive, so the sender can skip
+    // making an expensive message no one will read. Only advisory: the
+    // Receiver can be dropped right after this returns true, the message is
+    // then simply dropped along with the channel.
+    // A flag rather than Arc::strong_count, as MonitorHandles share the
+    // Channel too.
+    pub fn is_receiver_alive(&self) -> bool {
+        !self.channel.receiver_gone.load(Relaxed)
+    }
+
+    pub fn monitor(&self) -> MonitorHandle<T> {
+        MonitorHandle { channel: self.channel.clone() }
     }
 }
 
@@ -81,14 +269,292 @@ impl<T> Receiver<T> {
 
     // Once this is called, the Receiver object is consumed, as we
     // take the argument by value, rather than reference
-    // This can still panic, because the user might still call it
-    // before is_ready returns true
-    pub fn receive(self) -> T {
-        // swap used so drop knows whether there is an unread message
-        // that needs to be dropped
-        if !self.channel.ready.swap(false, Acquire) {
-            panic!("No message available!");
+    // Doesn't wait: gives RecvError::NotReady if called before is_ready
+    // returns true, and RecvError::SenderDropped if the Sender is gone
+    // without sending, so nothing will ever arrive. Use receive_with to wait.
+    pub fn receive(self) -> Result<T, RecvError> {
+        self.channel.take()
+    }
+
+    // Like receive, but if there's no message yet, gives the Receiver back,
+    // so the caller can try again later. Once the Sender is gone without
+    // sending, there's nothing to try again for, so that's SenderDropped.
+    pub fn try_receive(self) -> Result<T, TryRecvError<T>> {
+        match self.channel.take() {
+            Ok(message) => Ok(message),
+            Err(RecvError::NotReady) => Err(TryRecvError::NotReady(self)),
+            Err(RecvError::AlreadyConsumed) => Err(TryRecvError::AlreadyConsumed),
+            Err(RecvError::SenderDropped) => Err(TryRecvError::SenderDropped),
+        }
+    }
+
+    // Waits for the message using the given strategy, then receives it.
+    // With ParkStrategy, send unparks this thread, as does dropping the
+    // Sender without sending.
+    // Gives RecvError::SenderDropped instead if the Sender is gone without
+    // sending, rather than waiting forever.
+    pub fn receive_with<W: WakeStrategy>(self, strategy: W) -> Result<T, RecvError> {
+        *self.channel.waiter.lock().unwrap() = Some(thread::current());
+        wake::wait_until(&strategy, || self.is_ready() || self.channel.closed.load(Relaxed));
+        self.channel.waiter.lock().unwrap().take();
+        self.channel.take()
+    }
+
+    // Turns the Receiver into a future that any number of tasks can await,
+    // each getting its own clone of the message, like futures' Shared
+    pub fn shared(self) -> SharedReceiver<T> {
+        SharedReceiver {
+            shared: Arc::new(SharedState { receiver: Mutex::new(Some(self)), value: OnceLock::new() }),
+        }
+    }
+}
+
+// Polling takes the message out without consuming the Receiver, so it's
+// Ready(Err) for every poll after the one that got it
+impl<T> Pollable<T> for Receiver<T> {
+    fn poll_recv(&self) -> Poll<Result<T, poll::RecvError>> {
+        match self.channel.take() {
+            Ok(message) => Poll::Ready(Ok(message)),
+            Err(RecvError::NotReady) => Poll::Pending,
+            Err(RecvError::AlreadyConsumed | RecvError::SenderDropped) => Poll::Ready(Err(poll::RecvError)),
+        }
+    }
+}
+
+// Cloning it gives another handle to the same message, every clone resolves
+// to a clone of it once it's sent
+pub struct SharedReceiver<T> {
+    shared: Arc<SharedState<T>>,
+}
+
+struct SharedState<T> {
+    // Until the first poll that finds the message ready takes it out, and
+    // moves the message into value
+    receiver: Mutex<Option<Receiver<T>>>,
+    value: OnceLock<T>,
+}
+
+// Not derived, as that would require T: Clone even for the handle itself
+impl<T> Clone for SharedReceiver<T> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T: Clone> Future for SharedReceiver<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        if let Some(value) = self.shared.value.get() {
+            return Poll::Ready(value.clone());
+        }
+        let mut receiver = self.shared.receiver.lock().unwrap();
+        // Another task might have just taken the message, between our check
+        // and us getting the lock
+        let Some(r) = receiver.take() else {
+            drop(receiver);
+            return Poll::Ready(self.shared.value.get().unwrap().clone());
+        };
+        // Register before checking ready. The wakers lock orders this with
+        // send: either send takes our waker, or it was done before we locked,
+        // and we see ready below.
+        {
+            let mut wakers = r.channel.wakers.lock().unwrap();
+            if !wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                wakers.push(cx.waker().clone());
+            }
         }
-        unsafe { (*self.channel.message.get()).assume_init_read() }
+        if !r.is_ready() {
+            if r.channel.closed.load(Relaxed) {
+                panic!("Sender was dropped without sending!");
+            }
+            *receiver = Some(r);
+            return Poll::Pending;
+        }
+        // Locked and ready, so this can't fail
+        let value = self.shared.value.get_or_init(|| r.receive().unwrap()).clone();
+        drop(receiver);
+        Poll::Ready(value)
+    }
+}
+
+impl<T> MonitorHandle<T> {
+    // Only for observing progress, seeing true here doesn't give us access
+    // to anything the sender wrote, so Relaxed is enough
+    pub fn is_sent(&self) -> bool {
+        self.channel.sent.load(Relaxed)
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::test_util::block_on;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn second_take_is_already_consumed() {
+        let (s, r) = channel();
+        assert_eq!(r.channel.take(), Err(RecvError::NotReady));
+        s.send(String::from("once"));
+        assert_eq!(r.channel.take().as_deref(), Ok("once"));
+        assert_eq!(r.channel.take(), Err(RecvError::AlreadyConsumed));
+        // Dropping doesn't drop the message that was taken a second time
+        drop(r);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn monitor_sees_the_send() {
+        let (s, r) = channel();
+        let monitor = s.monitor();
+        assert!(!monitor.is_sent());
+        s.send(1);
+        assert!(monitor.is_sent());
+        // Stays sent after the message is received
+        r.receive().unwrap();
+        assert!(monitor.clone().is_sent());
+    }
+
+    #[test]
+    fn monitor_clones_without_t_clone() {
+        struct NotClone;
+        let (s, _r) = channel::<NotClone>();
+        let monitor = s.monitor().clone();
+        s.send(NotClone);
+        assert!(monitor.is_sent());
+    }
+
+    #[test]
+    fn shared_receiver_gives_every_task_a_clone() {
+        let (s, r) = channel::<String>();
+        let r = r.shared();
+        let r2 = r.clone();
+        thread::scope(|scope| {
+            let h1 = scope.spawn(|| block_on(r));
+            let h2 = scope.spawn(|| block_on(r2.clone()));
+            thread::sleep(Duration::from_millis(10));
+            s.send(String::from("shared"));
+            assert_eq!(h1.join().unwrap(), "shared");
+            assert_eq!(h2.join().unwrap(), "shared");
+        });
+        // Still there for a late clone
+        assert_eq!(block_on(r2), "shared");
+    }
+
+    #[test]
+    fn try_receive_gives_the_receiver_back() {
+        let (s, r) = channel();
+        let Err(TryRecvError::NotReady(r)) = r.try_receive() else {
+            panic!("Nothing was sent yet!");
+        };
+        s.send(4);
+        assert_eq!(r.try_receive().ok(), Some(4));
+    }
+
+    #[test]
+    fn try_receive_after_the_sender_is_gone() {
+        let (s, r) = channel::<i32>();
+        drop(s);
+        assert!(matches!(r.try_receive(), Err(TryRecvError::SenderDropped)));
+
+        let (s, r) = channel();
+        s.send(1);
+        assert_eq!(r.poll_recv(), Poll::Ready(Ok(1)));
+        assert!(matches!(r.try_receive(), Err(TryRecvError::AlreadyConsumed)));
+    }
+
+    #[test]
+    fn receive_before_send_is_not_ready() {
+        let (s, r) = channel::<i32>();
+        let Err(TryRecvError::NotReady(r)) = r.try_receive() else {
+            panic!("Nothing was sent yet!");
+        };
+        assert!(!r.is_ready());
+        drop(s);
+        assert_eq!(r.receive(), Err(RecvError::SenderDropped));
+    }
+
+    #[test]
+    fn cancel_wakes_a_parked_receiver() {
+        let (s, r) = channel::<String>();
+        thread::scope(|scope| {
+            let h = scope.spawn(|| r.receive_with(wake::ParkStrategy));
+            thread::sleep(Duration::from_millis(10));
+            s.cancel();
+            // Nothing else unparks it
+            assert_eq!(h.join().unwrap(), Err(RecvError::SenderDropped));
+        });
+    }
+
+    #[test]
+    fn dropping_the_sender_without_sending_wakes_a_parked_receiver() {
+        let (s, r) = channel::<String>();
+        thread::scope(|scope| {
+            let h = scope.spawn(|| r.receive_with(wake::ParkStrategy));
+            thread::sleep(Duration::from_millis(10));
+            drop(s);
+            assert_eq!(h.join().unwrap(), Err(RecvError::SenderDropped));
+        });
+    }
+
+    #[test]
+    fn send_wakes_a_parked_receiver() {
+        let (s, r) = channel();
+        thread::scope(|scope| {
+            let h = scope.spawn(|| r.receive_with(wake::ParkStrategy));
+            thread::sleep(Duration::from_millis(10));
+            s.send(1);
+            assert_eq!(h.join().unwrap(), Ok(1));
+        });
+    }
+
+    #[test]
+    fn try_send_to_a_dropped_receiver_gives_the_message_back() {
+        let (s, r) = channel();
+        drop(r);
+        assert_eq!(s.try_send(String::from("unsent")).unwrap_err().into_inner(), "unsent");
+        let (s, r) = channel();
+        assert!(s.try_send(2).is_ok());
+        assert_eq!(r.receive(), Ok(2));
+    }
+
+    #[test]
+    fn sender_sees_the_receiver_hang_up() {
+        let (s, r) = channel::<i32>();
+        // A monitor shares the channel too, and doesn't count as a receiver
+        let _monitor = s.monitor();
+        assert!(s.is_receiver_alive());
+        drop(r);
+        assert!(!s.is_receiver_alive());
+        // Sending anyway is fine, the message is dropped with the channel
+        s.send(1);
+    }
+
+    #[test]
+    fn zero_sized_messages_round_trip() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Relaxed);
+            }
+        }
+        let (s, r) = channel();
+        thread::scope(|scope| {
+            scope.spawn(|| s.send(()));
+        });
+        assert_eq!(r.receive(), Ok(()));
+        let (s, r) = channel();
+        s.send(Token);
+        let token = r.receive().unwrap();
+        assert_eq!(DROPS.load(Relaxed), 0);
+        drop(token);
+        assert_eq!(DROPS.load(Relaxed), 1);
+        // One never received is still dropped with the channel
+        let (s, r) = channel();
+        s.send(Token);
+        drop(r);
+        assert_eq!(DROPS.load(Relaxed), 2);
+    }
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+
+// A "channel" for events that carry no payload, where the receiver only cares
+// about how many happened since it last looked (e.g. to measure a rate).
+// Sending is a single fetch_add, there's no queue at all.
+//
+// Relaxed is enough everywhere, as the count is the only data, there's nothing
+// else that needs to become visible along with it.
+//
+// Overflow: The count wraps around on overflow, like fetch_add does. At a
+// billion events per second that takes over 500 years without a drain.
+pub struct CounterChannel {
+    count: AtomicU64,
+}
+
+impl Default for CounterChannel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CounterChannel {
+    pub const fn new() -> Self {
+        Self { count: AtomicU64::new(0) }
+    }
+
+    pub fn send(&self) {
+        self.count.fetch_add(1, Relaxed);
+    }
+
+    // Returns the number of sends since the last drain, and starts counting
+    // from zero again. swap makes sure no send is lost or counted twice in
+    // between reading and resetting.
+    pub fn drain_count(&self) -> u64 {
+        self.count.swap(0, Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn counts_every_send_exactly_once() {
+        let c = CounterChannel::new();
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    for _ in 0..10_000 {
+                        c.send();
+                    }
+                });
+            }
+        });
+        assert_eq!(c.drain_count(), 80_000);
+        assert_eq!(c.drain_count(), 0);
+    }
+
+    #[test]
+    fn draining_while_sending_loses_nothing() {
+        let c = CounterChannel::new();
+        let mut total = 0;
+        thread::scope(|s| {
+            let senders: Vec<_> = (0..4)
+                .map(|_| {
+                    s.spawn(|| {
+                        for _ in 0..10_000 {
+                            c.send();
+                        }
+                    })
+                })
+                .collect();
+            while senders.iter().any(|h| !h.is_finished()) {
+                total += c.drain_count();
+            }
+        });
+        total += c.drain_count();
+        assert_eq!(total, 40_000);
+    }
+
+    #[test]
+    fn wraps_on_overflow() {
+        let c = CounterChannel { count: AtomicU64::new(u64::MAX) };
+        c.send();
+        assert_eq!(c.drain_count(), 0);
+    }
+}
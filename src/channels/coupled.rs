@@ -0,0 +1,87 @@
+use crate::channels::mutex_based::Channel;
+
+// A request channel with room for FWD requests, coupled with an ack channel
+// with room for BACK acks, going the other way. Both are bounded, so a full
+// channel blocks whoever is sending on it.
+//
+// The capacities are checked at compile time: BACK has to be at least 1, and
+// FWD at least BACK, so the acks for all the requests that can be in flight
+// at once have room without the sides blocking each other. Getting this wrong
+// fails to build as soon as new is used, instead of deadlocking at runtime:
+/// ```compile_fail
+/// use rust_atomics_and_locks::channels::coupled::CoupledPair;
+/// let pair = CoupledPair::<u32, 1, 2>::new(); // FWD < BACK
+/// ```
+/// ```compile_fail
+/// use rust_atomics_and_locks::channels::coupled::CoupledPair;
+/// let pair = CoupledPair::<u32, 1, 0>::new(); // No room for acks
+/// ```
+pub struct CoupledPair<T, const FWD: usize, const BACK: usize> {
+    requests: Channel<T>,
+    acks: Channel<()>,
+}
+
+impl<T, const FWD: usize, const BACK: usize> Default for CoupledPair<T, FWD, BACK> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const FWD: usize, const BACK: usize> CoupledPair<T, FWD, BACK> {
+    // Evaluated at compile time, for every FWD and BACK new is used with
+    const VALID_CAPACITIES: () = {
+        assert!(BACK >= 1, "The ack channel needs room for at least 1 ack!");
+        assert!(FWD >= BACK, "The request channel can't be smaller than the ack channel!");
+    };
+
+    pub fn new() -> Self {
+        let () = Self::VALID_CAPACITIES;
+        Self {
+            requests: Channel::with_config(FWD, Some(FWD)),
+            acks: Channel::with_config(BACK, Some(BACK)),
+        }
+    }
+
+    // Blocks while FWD requests are queued
+    pub fn send_request(&self, request: T) {
+        self.requests.send(request);
+    }
+
+    pub fn receive_request(&self) -> T {
+        self.requests.receive()
+    }
+
+    // Acknowledges a received request, blocks while BACK acks are queued
+    pub fn ack(&self) {
+        self.acks.send(());
+    }
+
+    // Blocks until a request is acknowledged
+    pub fn receive_ack(&self) {
+        self.acks.receive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn request_and_ack_round_trip() {
+        let pair = CoupledPair::<String, 2, 1>::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for _ in 0..3 {
+                    let request = pair.receive_request();
+                    assert!(request.starts_with("request"));
+                    pair.ack();
+                }
+            });
+            for i in 0..3 {
+                pair.send_request(format!("request {i}"));
+                pair.receive_ack();
+            }
+        });
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+// Credit based flow control: the sender starts out with a fixed number of
+// credits, and every send uses one up. Receiving a message gives back a
+// Credit along with it, and only once the receiver drops that Credit (when
+// it's done processing the message) does the sender get the credit back.
+// Once the credits run out, send blocks until one comes back.
+//
+// Unlike a bounded queue, which only limits how many messages are waiting,
+// this limits how many are being worked on, so the sender can't get further
+// ahead of the receiver than the number of credits, no matter how slowly the
+// receiver processes them.
+pub fn channel<T>(credits: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(credits > 0, "Need at least 1 credit!");
+    let shared = Arc::new(Shared {
+        queue: Mutex::new(VecDeque::new()),
+        item_ready: Condvar::new(),
+        credits: Arc::new(Credits {
+            limit: credits,
+            outstanding: AtomicUsize::new(0),
+            lock: Mutex::new(()),
+            returned: Condvar::new(),
+        }),
+    });
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Gives its credit back to the sender when dropped
+pub struct Credit {
+    credits: Arc<Credits>,
+}
+
+struct Shared<T> {
+    queue: Mutex<VecDeque<T>>,
+    item_ready: Condvar,
+    // In its own Arc, so a Credit doesn't depend on T
+    credits: Arc<Credits>,
+}
+
+struct Credits {
+    limit: usize,
+    // Messages sent whose Credit hasn't been dropped yet, queued ones
+    // included. Nothing else is synchronized through this, so Relaxed is
+    // enough.
+    outstanding: AtomicUsize,
+    // Only for waiting on returned, the count itself is atomic
+    lock: Mutex<()>,
+    returned: Condvar,
+}
+
+impl<T> Sender<T> {
+    // Blocks while every credit is in use
+    pub fn send(&self, message: T) {
+        let credits = &*self.shared.credits;
+        let mut guard = credits.lock.lock().unwrap();
+        // Only one Sender, so the count can only go down between our check and
+        // the increment, never up
+        while credits.outstanding.load(Relaxed) >= credits.limit {
+            guard = credits.returned.wait(guard).unwrap();
+        }
+        credits.outstanding.fetch_add(1, Relaxed);
+        drop(guard);
+        self.shared.queue.lock().unwrap().push_back(message);
+        self.shared.item_ready.notify_one();
+    }
+
+    // How many more messages can be sent right now without blocking
+    pub fn available_credits(&self) -> usize {
+        let credits = &*self.shared.credits;
+        credits.limit.saturating_sub(credits.outstanding.load(Relaxed))
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until a message is available. Drop the Credit once done with the
+    // message, holding on to it holds up the sender.
+    pub fn receive(&self) -> (T, Credit) {
+        let mut queue = self.shared.queue.lock().unwrap();
+        let message = loop {
+            if let Some(message) = queue.pop_front() {
+                break message;
+            }
+            queue = self.shared.item_ready.wait(queue).unwrap();
+        };
+        (message, Credit { credits: self.shared.credits.clone() })
+    }
+}
+
+impl Drop for Credit {
+    fn drop(&mut self) {
+        self.credits.outstanding.fetch_sub(1, Relaxed);
+        // Taking the lock makes sure a sender that just saw no credits left is
+        // already waiting, so it doesn't miss this
+        drop(self.credits.lock.lock().unwrap());
+        self.credits.returned.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn third_send_waits_for_a_credit() {
+        let (tx, rx) = channel(2);
+        tx.send(1);
+        tx.send(2);
+        assert_eq!(tx.available_credits(), 0);
+        // Receiving isn't enough, the credit has to be dropped
+        let (first, credit) = rx.receive();
+        assert_eq!(first, 1);
+        let sent = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                tx.send(3);
+                sent.store(true, Relaxed);
+            });
+            thread::sleep(Duration::from_millis(20));
+            assert!(!sent.load(Relaxed));
+            drop(credit);
+        });
+        assert!(sent.load(Relaxed));
+        assert_eq!(rx.receive().0, 2);
+        assert_eq!(rx.receive().0, 3);
+        assert_eq!(tx.available_credits(), 2);
+    }
+}
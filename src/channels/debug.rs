@@ -0,0 +1,294 @@
+// Debugging help for hangs: a watchdog thread that looks for blocking channels
+// whose receivers have been waiting for a long time without any message
+// getting through, and reports them.
+//
+// Only does anything with the deadlock-detector feature enabled. Without it,
+// Watch is an empty struct and its methods are empty functions, so channels
+// can use it unconditionally without paying for it.
+//
+// With the feature, every channel that has a Watch registers a WaitState in a
+// global registry when it's created. The registry only holds Weak references,
+// so it doesn't keep anything alive. Dropped channels are cleaned up on the
+// watchdog's next scan, and by Watch::new, so the registry doesn't grow
+// without a watchdog running either.
+
+#[cfg(feature = "deadlock-detector")]
+use std::sync::{Arc, Mutex, OnceLock, Weak};
+#[cfg(feature = "deadlock-detector")]
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize};
+#[cfg(feature = "deadlock-detector")]
+use std::sync::atomic::Ordering::Relaxed;
+#[cfg(feature = "deadlock-detector")]
+use std::thread::{self, JoinHandle};
+#[cfg(feature = "deadlock-detector")]
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "deadlock-detector")]
+static REGISTRY: Mutex<Vec<Weak<WaitState>>> = Mutex::new(Vec::new());
+// A panic while holding the lock doesn't leave the registry in a bad state,
+// so just ignore poisoning
+#[cfg(feature = "deadlock-detector")]
+fn lock_registry() -> std::sync::MutexGuard<'static, Vec<Weak<WaitState>>> {
+    REGISTRY.lock().unwrap_or_else(|e| e.into_inner())
+}
+#[cfg(feature = "deadlock-detector")]
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+// Times are stored as nanoseconds since this instant, so they fit in an atomic
+#[cfg(feature = "deadlock-detector")]
+fn now_nanos() -> u64 {
+    static START: OnceLock<Instant> = OnceLock::new();
+    START.get_or_init(Instant::now).elapsed().as_nanos() as u64
+}
+
+// Everything here is just for reporting, nothing else is synchronized through
+// it, so Relaxed is enough
+#[cfg(feature = "deadlock-detector")]
+struct WaitState {
+    id: usize,
+    name: &'static str,
+    waiters: AtomicUsize,
+    // When a message was last received, or when a receiver started waiting
+    // after there were none waiting
+    last_progress: AtomicU64,
+    // last_progress as of the last report, so a stall is only reported once
+    last_reported: AtomicU64,
+}
+
+// What a blocking channel holds to let the detector see it
+pub(crate) struct Watch {
+    #[cfg(feature = "deadlock-detector")]
+    state: Arc<WaitState>,
+}
+
+impl Watch {
+    // The name (usually the channel's type name) ends up in reports, to tell
+    // channels apart
+    pub(crate) fn new(name: &'static str) -> Self {
+        #[cfg(feature = "deadlock-detector")]
+        {
+            let state = Arc::new(WaitState {
+                id: NEXT_ID.fetch_add(1, Relaxed),
+                name,
+                waiters: AtomicUsize::new(0),
+                last_progress: AtomicU64::new(now_nanos()),
+                last_reported: AtomicU64::new(u64::MAX),
+            });
+            let mut registry = lock_registry();
+            // Only when it would have to grow, so it stays within about twice
+            // the number of live channels, for a scan per doubling, not per
+            // channel
+            if registry.len() == registry.capacity() {
+                registry.retain(|state| state.strong_count() > 0);
+            }
+            registry.push(Arc::downgrade(&state));
+            drop(registry);
+            Self { state }
+        }
+        #[cfg(not(feature = "deadlock-detector"))]
+        {
+            let _ = name;
+            Self {}
+        }
+    }
+
+    // Call right before a receiver starts waiting
+    #[inline]
+    pub(crate) fn start_waiting(&self) {
+        #[cfg(feature = "deadlock-detector")]
+        if self.state.waiters.fetch_add(1, Relaxed) == 0 {
+            self.state.last_progress.store(now_nanos(), Relaxed);
+        }
+    }
+
+    // Call when a waiting receiver got its message
+    #[inline]
+    pub(crate) fn stop_waiting(&self) {
+        #[cfg(feature = "deadlock-detector")]
+        {
+            self.state.waiters.fetch_sub(1, Relaxed);
+            self.state.last_progress.store(now_nanos(), Relaxed);
+        }
+    }
+}
+
+// The running watchdog, dropping it stops the thread and waits for it to
+// finish
+#[cfg(feature = "deadlock-detector")]
+pub struct DeadlockDetector {
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+#[cfg(feature = "deadlock-detector")]
+impl Drop for DeadlockDetector {
+    fn drop(&mut self) {
+        self.stop.store(true, Relaxed);
+        let thread = self.thread.take().unwrap();
+        // So it doesn't sleep out the rest of its interval first
+        thread.thread().unpark();
+        // A panic in report already got printed, don't panic again for it
+        let _ = thread.join();
+    }
+}
+
+// Spawns the watchdog, which checks every registered channel a few times per
+// threshold, and prints a warning to stderr for every channel that has
+// receivers waiting with no progress for longer than threshold.
+// Runs until the DeadlockDetector is dropped.
+#[cfg(feature = "deadlock-detector")]
+pub fn install_deadlock_detector(threshold: Duration) -> DeadlockDetector {
+    install_deadlock_detector_with(threshold, |warning| eprintln!("{warning}"))
+}
+
+// Same, but gives the warnings to report instead of printing them
+#[cfg(feature = "deadlock-detector")]
+pub fn install_deadlock_detector_with(threshold: Duration, report: impl Fn(String) + Send + 'static) -> DeadlockDetector {
+    let interval = (threshold / 4).max(Duration::from_millis(1));
+    let threshold = threshold.as_nanos() as u64;
+    let stop = Arc::new(AtomicBool::new(false));
+    let stopped = stop.clone();
+    let thread = thread::spawn(move || loop {
+        // Waking up early (spuriously) just means an early scan
+        thread::park_timeout(interval);
+        if stopped.load(Relaxed) {
+            return;
+        }
+        let now = now_nanos();
+        // Don't hold the registry lock while reporting, report might be slow,
+        // and channels being created have to wait for the lock
+        let live: Vec<_> = {
+            let mut registry = lock_registry();
+            registry.retain(|state| state.strong_count() > 0);
+            registry.iter().filter_map(Weak::upgrade).collect()
+        };
+        for state in live {
+            let waiters = state.waiters.load(Relaxed);
+            let last_progress = state.last_progress.load(Relaxed);
+            let stalled_for = now.saturating_sub(last_progress);
+            if waiters > 0 && stalled_for > threshold && state.last_reported.load(Relaxed) != last_progress {
+                state.last_reported.store(last_progress, Relaxed);
+                report(format!(
+                    "possible deadlock: channel #{} ({}) has {} receiver(s) waiting for {:?} without progress",
+                    state.id,
+                    state.name,
+                    waiters,
+                    Duration::from_nanos(stalled_for),
+                ));
+            }
+        }
+    });
+    DeadlockDetector { stop, thread: Some(thread) }
+}
+
+// Catches lost wakeups in debug builds: every notification a channel sends
+// out for a new message takes the next notification sequence number, and
+// every message a receive takes out of the queue takes the next consumed
+// sequence number. Once nothing is running, the two must be exactly as far
+// apart as there are messages queued: a message that went into the queue
+// without a notification, which a receiver could wait forever for, shows up
+// as a gap, fewer notifications than messages.
+// While sends and receives are running the two can be off either way for a
+// moment (a send notifies after unlocking, so a receiver can already have
+// taken the message), so this is only checked when asked, by tests.
+//
+// Only counts in debug builds, in release builds it's an empty struct, like
+// Watch without the deadlock-detector feature.
+// Just counters, nothing is synchronized through them, so Relaxed is enough.
+pub(crate) struct NotifySeq {
+    #[cfg(debug_assertions)]
+    notified: std::sync::atomic::AtomicU64,
+    #[cfg(debug_assertions)]
+    consumed: std::sync::atomic::AtomicU64,
+}
+
+impl NotifySeq {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(debug_assertions)]
+            notified: std::sync::atomic::AtomicU64::new(0),
+            #[cfg(debug_assertions)]
+            consumed: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    // Call right where the notification for one new message goes out
+    #[inline]
+    pub(crate) fn notified(&self) {
+        #[cfg(debug_assertions)]
+        self.notified.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    // Call when a receive takes n messages out of the queue
+    #[inline]
+    pub(crate) fn consumed(&self, n: usize) {
+        #[cfg(debug_assertions)]
+        self.consumed.fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+        #[cfg(not(debug_assertions))]
+        let _ = n;
+    }
+
+    // Notifications sent minus messages taken, which should be how many
+    // messages are queued once nothing is running. Negative while a receiver
+    // got to a message before its sender notified.
+    #[cfg(debug_assertions)]
+    pub(crate) fn unconsumed(&self) -> i64 {
+        use std::sync::atomic::Ordering::Relaxed;
+        self.notified.load(Relaxed) as i64 - self.consumed.load(Relaxed) as i64
+    }
+}
+
+#[cfg(all(test, feature = "deadlock-detector"))]
+mod tests {
+    use super::*;
+    use crate::channels::mutex_based::Channel;
+    use std::sync::mpsc;
+
+    #[test]
+    fn reports_a_stuck_receiver() {
+        // Its own message type, so the warning can be told apart from ones
+        // about other tests' channels, which are in the same registry
+        struct NeverSent;
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let _detector = install_deadlock_detector_with(Duration::from_millis(30), move |warning| {
+            if warning.contains("NeverSent") {
+                let _ = tx.lock().unwrap().send(warning);
+            }
+        });
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| c.receive());
+            let warning = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+            assert!(warning.starts_with("possible deadlock"), "{warning}");
+            assert!(warning.contains("1 receiver(s) waiting"), "{warning}");
+            // Only reported once per stall
+            assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+            c.send(NeverSent);
+        });
+    }
+
+    #[test]
+    fn dropping_the_detector_stops_it() {
+        let reports = Arc::new(AtomicUsize::new(0));
+        let counted = reports.clone();
+        // Long enough that the drop has to wake it up to stop it in time
+        let detector = install_deadlock_detector_with(Duration::from_secs(60), move |_| {
+            counted.fetch_add(1, Relaxed);
+        });
+        let start = Instant::now();
+        drop(detector);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        // The thread is gone, so nothing holds report anymore
+        assert_eq!(Arc::strong_count(&reports), 1);
+    }
+
+    #[test]
+    fn new_channels_clean_up_dropped_ones() {
+        for _ in 0..10_000 {
+            drop(Channel::<()>::new());
+        }
+        // Other tests' channels are in here too, but nowhere near this many
+        assert!(lock_registry().len() < 1000);
+    }
+}
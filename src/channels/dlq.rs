@@ -0,0 +1,77 @@
+use std::sync::mpsc;
+use crate::channels::mutex_based;
+
+// A mutex_based channel with a dead letter queue: messages that are still
+// in the channel when it's dropped are sent on into the dead letter channel,
+// instead of being dropped with it, so undelivered messages from any number
+// of channels can be dealt with in one place.
+//
+// The dead letter side is a std channel, like in interop, as its send tells
+// us when there's no one left to receive, in which case the messages are
+// dropped after all.
+pub struct Channel<T> {
+    inner: mutex_based::Channel<T>,
+    dead_letter: mpsc::Sender<T>,
+}
+
+impl<T> Channel<T> {
+    pub fn new_with_dlq(dead_letter: mpsc::Sender<T>) -> Self {
+        Self { inner: mutex_based::Channel::new(), dead_letter }
+    }
+
+    pub fn send(&self, message: T) {
+        self.inner.send(message);
+    }
+
+    pub fn receive(&self) -> T {
+        self.inner.receive()
+    }
+}
+
+// Forwarded in the order they were sent
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        let mut undelivered = Vec::new();
+        self.inner.drain_into(&mut undelivered);
+        for message in undelivered {
+            // The dead letter receiver is gone, the message comes back in the
+            // error and is dropped with it, as are the rest
+            if self.dead_letter.send(message).is_err() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unreceived_messages_go_to_the_dead_letter_queue() {
+        let (dead_letter, dead_letters) = mpsc::channel();
+        let c = Channel::new_with_dlq(dead_letter.clone());
+        c.send(1);
+        c.send(2);
+        c.send(3);
+        assert_eq!(c.receive(), 1);
+        drop(c);
+        // Another channel sharing the same dead letter queue
+        let c = Channel::new_with_dlq(dead_letter);
+        c.send(4);
+        drop(c);
+        assert_eq!(dead_letters.try_iter().collect::<Vec<_>>(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn closed_dead_letter_queue_drops_the_messages() {
+        let (dead_letter, dead_letters) = mpsc::channel();
+        drop(dead_letters);
+        let message = std::sync::Arc::new(());
+        let c = Channel::new_with_dlq(dead_letter);
+        c.send(message.clone());
+        c.send(message.clone());
+        drop(c);
+        assert_eq!(std::sync::Arc::strong_count(&message), 1);
+    }
+}
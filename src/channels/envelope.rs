@@ -0,0 +1,91 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use std::time::Instant;
+use crate::channels::mutex_based;
+
+// Wraps every message in an Envelope with some tracing info, so users don't
+// have to add fields for that to their own message types.
+pub struct Envelope<T> {
+    pub payload: T,
+    pub metadata: Metadata,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Metadata {
+    // When send_enveloped was called
+    pub timestamp: Instant,
+    // Counts up from 0 for every message sent through the channel
+    pub sequence: u64,
+}
+
+// A mutex_based::Channel that puts messages in an Envelope on send.
+// With more than one sender, two sends racing can get queued in the opposite
+// order of their sequence numbers, as the number is taken before the message is
+// queued. With a single sender they always come out in order.
+pub struct EnvelopeChannel<T> {
+    channel: mutex_based::Channel<Envelope<T>>,
+    next_sequence: AtomicU64,
+}
+
+impl<T> Default for EnvelopeChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> EnvelopeChannel<T> {
+    pub fn new() -> Self {
+        Self {
+            channel: mutex_based::Channel::new(),
+            next_sequence: AtomicU64::new(0),
+        }
+    }
+
+    // Relaxed is enough for the sequence number, fetch_add alone makes sure
+    // every message gets a different one
+    pub fn send_enveloped(&self, payload: T) {
+        let metadata = Metadata {
+            timestamp: Instant::now(),
+            sequence: self.next_sequence.fetch_add(1, Relaxed),
+        };
+        self.channel.send(Envelope { payload, metadata });
+    }
+
+    // Blocks like mutex_based::Channel::receive
+    pub fn receive_enveloped(&self) -> Envelope<T> {
+        self.channel.receive()
+    }
+
+    // For receivers that don't care about the metadata
+    pub fn receive(&self) -> T {
+        self.receive_enveloped().payload
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sequence_numbers_and_timestamps() {
+        let c = EnvelopeChannel::new();
+        for i in 0..3 {
+            c.send_enveloped(i * 10);
+        }
+        let received: Vec<_> = (0..3).map(|_| c.receive_enveloped()).collect();
+        let sequences: Vec<_> = received.iter().map(|e| e.metadata.sequence).collect();
+        assert_eq!(sequences, [0, 1, 2]);
+        assert!(received.windows(2).all(|w| w[0].metadata.timestamp <= w[1].metadata.timestamp));
+        assert_eq!(received.iter().map(|e| e.payload).collect::<Vec<_>>(), [0, 10, 20]);
+    }
+
+    #[test]
+    fn receive_strips_the_envelope() {
+        let c = EnvelopeChannel::new();
+        c.send_enveloped("payload");
+        assert_eq!(c.receive(), "payload");
+        // The sequence keeps counting across receives
+        c.send_enveloped("next");
+        assert_eq!(c.receive_enveloped().metadata.sequence, 1);
+    }
+}
@@ -0,0 +1,85 @@
+use crate::channels::mutex_based;
+
+// Anything with a blocking receive, like ReceiveTimeout in iter for receive
+// with a timeout
+pub trait Receive<T> {
+    fn receive(&self) -> T;
+}
+
+impl<T> Receive<T> for mutex_based::Channel<T> {
+    fn receive(&self) -> T {
+        mutex_based::Channel::receive(self)
+    }
+}
+
+// So Filtered can borrow a channel instead of taking it
+impl<T, R: Receive<T> + ?Sized> Receive<T> for &R {
+    fn receive(&self) -> T {
+        (**self).receive()
+    }
+}
+
+// A receiver that throws away every message pred returns true for, at the
+// receiving end, for consumers that aren't interested in some of the messages.
+// Thrown away messages are dropped right away, so their destructors run.
+pub struct Filtered<R, P> {
+    receiver: R,
+    pred: P,
+}
+
+impl<R, P> Filtered<R, P> {
+    pub fn new(receiver: R, pred: P) -> Self {
+        Self { receiver, pred }
+    }
+
+    // Blocks until there's a message pred returns false for, throwing away
+    // every message before it that it returns true for
+    pub fn receive<T>(&self) -> T
+    where
+        R: Receive<T>,
+        P: Fn(&T) -> bool,
+    {
+        loop {
+            let message = self.receiver.receive();
+            if !(self.pred)(&message) {
+                return message;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Counted(u32);
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Relaxed);
+        }
+    }
+
+    #[test]
+    fn skips_and_drops_filtered_messages() {
+        let c = mutex_based::Channel::new();
+        for i in 1..=6 {
+            c.send(Counted(i));
+        }
+        let odd = Filtered::new(&c, |m: &Counted| m.0.is_multiple_of(2));
+        let first = odd.receive();
+        assert_eq!(first.0, 1);
+        assert_eq!(DROPS.load(Relaxed), 0);
+        let second = odd.receive();
+        assert_eq!(second.0, 3);
+        // 2, thrown away on the way to 3
+        assert_eq!(DROPS.load(Relaxed), 1);
+        assert_eq!(odd.receive().0, 5);
+        assert_eq!(DROPS.load(Relaxed), 3);
+        // 6 is still queued, it's up to whoever drops the channel
+        assert_eq!(c.len(), 1);
+    }
+}
@@ -0,0 +1,225 @@
+use std::cell::UnsafeCell;
+use std::hint;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use crate::channels::leak_check::Tracker;
+
+// A oneshot that can be reset and reused, where every reset starts a new
+// generation. Senders are issued for the generation that's current at the time,
+// so a sender that's held on to (or delayed) past a reset can't write into the
+// next generation's slot, its send is rejected as stale instead.
+//
+// The generation and the slot's state are packed into one AtomicU64, so that
+// checking the generation and claiming the slot is a single compare exchange.
+// If they were separate, a reset could happen right in between a sender
+// checking the generation and claiming the slot.
+// The lowest two bits are the state, the rest is the generation.
+//
+// Cons: Only 62 bits of generation, which is still far more resets than
+// anyone will do
+pub struct Channel<T> {
+    message: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicU64,
+    tracker: Tracker,
+}
+
+// No message yet, a sender for this generation can claim the slot
+const EMPTY: u64 = 0;
+// A sender is writing the message
+const WRITING: u64 = 1;
+// The message is ready to be received (or dropped by a reset)
+const READY: u64 = 2;
+// A receiver (or a reset) is reading the message out of the slot
+const READING: u64 = 3;
+const STATE_BITS: u64 = 2;
+const STATE_MASK: u64 = (1 << STATE_BITS) - 1;
+
+const fn pack(generation: u64, state: u64) -> u64 {
+    generation << STATE_BITS | state
+}
+
+pub struct Sender<'a, T> {
+    channel: &'a Channel<T>,
+    generation: u64,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendError<T> {
+    // The channel was reset after this sender was issued
+    Stale(T),
+    // Another sender of the same generation already sent
+    AlreadySent(T),
+}
+
+unsafe impl<T> Sync for Channel<T> where T: Send {}
+
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() & STATE_MASK == READY {
+            unsafe { self.message.get_mut().assume_init_drop() }
+            self.tracker.message_gone();
+        }
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Channel<T> {
+    pub const fn new() -> Self {
+        Self {
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+            state: AtomicU64::new(pack(0, EMPTY)),
+            tracker: Tracker::new(),
+        }
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.state.load(Relaxed) >> STATE_BITS
+    }
+
+    // Issues a sender for the current generation
+    pub fn sender(&self) -> Sender<'_, T> {
+        Sender { channel: self, generation: self.generation() }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state.load(Relaxed) & STATE_MASK == READY
+    }
+
+    /// Returns None if no message is ready for the current generation
+    pub fn try_receive(&self) -> Option<T> {
+        let generation = self.generation();
+        // Acquire pairs with the Release store in send
+        self.state
+            .compare_exchange(pack(generation, READY), pack(generation, READING), Acquire, Relaxed)
+            .ok()?;
+        // Safety: We've claimed the message by moving the state to READING,
+        // nothing else touches the slot until we move the state on
+        let message = unsafe { (*self.message.get()).assume_init_read() };
+        self.tracker.message_gone();
+        // Back to EMPTY, but senders of this generation have either sent already
+        // or are free to send again, like a fresh oneshot
+        self.state.store(pack(generation, EMPTY), Release);
+        Some(message)
+    }
+
+    // Starts a new generation, dropping the current message if there is one.
+    // Senders issued before this are stale from now on.
+    // If a send or receive is in progress, waits for it to finish first.
+    pub fn reset(&self) {
+        let mut current = self.state.load(Relaxed);
+        loop {
+            let generation = current >> STATE_BITS;
+            let next = pack(generation.wrapping_add(1), EMPTY);
+            match current & STATE_MASK {
+                WRITING | READING => {
+                    hint::spin_loop();
+                    current = self.state.load(Relaxed);
+                }
+                READY => {
+                    // Claim the message like try_receive does, so we can drop it
+                    match self.state.compare_exchange(current, pack(generation, READING), Acquire, Relaxed) {
+                        Ok(_) => {
+                            unsafe { (*self.message.get()).assume_init_drop() }
+                            self.tracker.message_gone();
+                            self.state.store(next, Release);
+                            return;
+                        }
+                        Err(c) => current = c,
+                    }
+                }
+                _ => match self.state.compare_exchange(current, next, Acquire, Relaxed) {
+                    Ok(_) => return,
+                    Err(c) => current = c,
+                },
+            }
+        }
+    }
+}
+
+impl<T> Sender<'_, T> {
+    /// Gives the message back if this sender is from a previous generation, or
+    /// if a message has already been sent in this generation
+    pub fn send(self, message: T) -> Result<(), SendError<T>> {
+        let channel = self.channel;
+        // Acquire, so whoever emptied the slot before us is done with it
+        if let Err(current) = channel.state.compare_exchange(
+            pack(self.generation, EMPTY),
+            pack(self.generation, WRITING),
+            Acquire,
+            Relaxed,
+        ) {
+            if current >> STATE_BITS != self.generation {
+                return Err(SendError::Stale(message));
+            }
+            return Err(SendError::AlreadySent(message));
+        }
+        // Safety: We've claimed the slot by moving the state to WRITING
+        unsafe { (*channel.message.get()).write(message); }
+        channel.tracker.message_sent();
+        channel.state.store(pack(self.generation, READY), Release);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn stale_sender_is_rejected() {
+        let c = Channel::new();
+        let old = c.sender();
+        c.reset();
+        assert_eq!(c.generation(), 1);
+        assert_eq!(old.send(String::from("late")), Err(SendError::Stale(String::from("late"))));
+        assert!(!c.is_ready());
+        c.sender().send(String::from("fresh")).unwrap();
+        assert_eq!(c.try_receive().as_deref(), Some("fresh"));
+        assert_eq!(c.try_receive(), None);
+    }
+
+    #[test]
+    fn one_send_per_message() {
+        let c = Channel::new();
+        c.sender().send(1).unwrap();
+        assert_eq!(c.sender().send(2), Err(SendError::AlreadySent(2)));
+        assert_eq!(c.try_receive(), Some(1));
+    }
+
+    #[test]
+    fn reset_drops_the_message() {
+        let a = Arc::new(());
+        let c = Channel::new();
+        c.sender().send(a.clone()).unwrap();
+        c.reset();
+        assert_eq!(Arc::strong_count(&a), 1);
+        assert_eq!(c.try_receive(), None);
+        c.sender().send(a.clone()).unwrap();
+        drop(c);
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn racing_reset_never_lets_a_stale_message_through() {
+        for _ in 0..200 {
+            let c = Channel::new();
+            let old = c.sender();
+            std::thread::scope(|s| {
+                s.spawn(|| {
+                    let _ = old.send(0);
+                });
+                c.reset();
+            });
+            // Whether the old send got in before the reset or not, the new
+            // generation never sees its message
+            assert_eq!(c.try_receive(), None);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::{Arc, Mutex};
+use crate::channels::mutex_based::Channel;
+
+// Keeps track of a subsystem's channels, so tearing the subsystem down is one
+// shutdown_all to tell every receiver to stop, and one drain_all to clean up
+// whatever was left in the queues, instead of doing both for every channel.
+//
+// The group shares the channels, it doesn't have to be the only owner, so
+// channels can be handed out to senders and receivers as usual.
+pub struct ChannelGroup<T> {
+    channels: Mutex<Vec<Arc<Channel<T>>>>,
+}
+
+impl<T> Default for ChannelGroup<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> ChannelGroup<T> {
+    pub fn new() -> Self {
+        Self { channels: Mutex::new(Vec::new()) }
+    }
+
+    // Makes a new channel that's part of the group
+    pub fn channel(&self) -> Arc<Channel<T>> {
+        let channel = Arc::new(Channel::new());
+        self.add(channel.clone());
+        channel
+    }
+
+    // Adds a channel made elsewhere, with_config or new_fair, say
+    pub fn add(&self, channel: Arc<Channel<T>>) {
+        self.channels.lock().unwrap().push(channel);
+    }
+
+    pub fn len(&self) -> usize {
+        self.channels.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.lock().unwrap().is_empty()
+    }
+
+    // Closes every channel in the group, waking all their waiting receivers.
+    // Those still get what's queued, and give up once it's gone, see
+    // Channel::close.
+    // Holding the group lock means a channel can't be added halfway through,
+    // and miss the shutdown. One added after this is open, as usual.
+    pub fn shutdown_all(&self) {
+        for channel in self.channels.lock().unwrap().iter() {
+            channel.close();
+        }
+    }
+
+    // Takes every message still queued in any of the channels, one channel
+    // after the other in the order they were added, each channel's messages in
+    // the order they were sent. Like Channel::drain, doesn't wait for more.
+    pub fn drain_all(&self) -> Vec<T> {
+        let mut messages = Vec::new();
+        for channel in self.channels.lock().unwrap().iter() {
+            channel.drain_into(&mut messages);
+        }
+        messages
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::mutex_based::RecvTimeoutError;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn shutdown_all_then_drain_all_cleans_up_every_channel() {
+        let group = ChannelGroup::new();
+        let channels = [group.channel(), group.channel(), group.channel()];
+        assert_eq!(group.len(), 3);
+        for (i, channel) in channels.iter().enumerate() {
+            channel.send(i * 10);
+            channel.send(i * 10 + 1);
+        }
+        group.shutdown_all();
+        assert_eq!(group.drain_all(), [0, 1, 10, 11, 20, 21]);
+        for channel in &channels {
+            assert!(channel.is_closed());
+            // Gives up right away instead of waiting
+            assert_eq!(channel.recv_timeout(Duration::from_secs(10)), Err(RecvTimeoutError::Disconnected));
+        }
+    }
+
+    #[test]
+    fn shutdown_all_wakes_waiting_receivers() {
+        let group = ChannelGroup::<i32>::new();
+        let channels = [group.channel(), group.channel()];
+        thread::scope(|s| {
+            let receivers: Vec<_> = channels
+                .iter()
+                .map(|c| s.spawn(|| c.recv_timeout(Duration::from_secs(10))))
+                .collect();
+            thread::sleep(Duration::from_millis(20));
+            group.shutdown_all();
+            for r in receivers {
+                assert_eq!(r.join().unwrap(), Err(RecvTimeoutError::Disconnected));
+            }
+        });
+    }
+
+    #[test]
+    fn add_takes_channels_made_elsewhere() {
+        let group = ChannelGroup::default();
+        assert!(group.is_empty());
+        let fair = Arc::new(Channel::new_fair());
+        group.add(fair.clone());
+        fair.send("left over");
+        group.shutdown_all();
+        assert!(fair.is_closed());
+        assert_eq!(group.drain_all(), ["left over"]);
+    }
+}
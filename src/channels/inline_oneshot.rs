@@ -0,0 +1,149 @@
+use std::mem::{align_of, size_of};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+// Same interface as safer_oneshot, but for messages that fit in a usize.
+// Instead of an UnsafeCell<MaybeUninit<T>> next to the flags, the message is
+// copied bit for bit into an AtomicUsize.
+//
+// Pros:
+// 1. No UnsafeCell, the message itself is read and written atomically, so
+// there's no way to get a data race on it
+// 2. No Drop needed, the message is Copy
+// Cons: Only works for the types that implement Inline, anything else has to
+// go through safer_oneshot
+//
+// Rust doesn't let us pick the storage based on the size of T for one generic
+// Channel<T> (that would need specialization), so the small types get their
+// own channel instead.
+
+/// Types that can be stored in the channel's AtomicUsize.
+///
+/// # Safety
+/// Implementors must be Copy, fit in a usize (size and alignment),
+/// and have no padding bytes, as every byte of the value ends up in the
+/// atomic. They also must not be or contain pointers, as going through a
+/// usize loses the pointer's provenance.
+pub unsafe trait Inline: Copy {}
+
+macro_rules! impl_inline {
+    ($($t:ty),*) => {
+        $(unsafe impl Inline for $t {})*
+    };
+}
+
+impl_inline!(u8, u16, u32, usize, i8, i16, i32, isize, bool, char);
+#[cfg(target_pointer_width = "64")]
+impl_inline!(u64, i64);
+
+pub struct Channel<T: Inline> {
+    message: AtomicUsize,
+    in_use: AtomicBool,
+    ready: AtomicBool,
+    _marker: std::marker::PhantomData<T>,
+}
+
+impl<T: Inline> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Inline> Channel<T> {
+    pub const fn new() -> Self {
+        // Checked at compile time for every T the channel is used with, in case
+        // some Inline impl got it wrong
+        const {
+            assert!(size_of::<T>() <= size_of::<usize>());
+            assert!(align_of::<T>() <= align_of::<usize>());
+        }
+        Self {
+            message: AtomicUsize::new(0),
+            in_use: AtomicBool::new(false),
+            ready: AtomicBool::new(false),
+            _marker: std::marker::PhantomData,
+        }
+    }
+
+    /// Panics when trying to send more than one message
+    pub fn send(&self, message: T) {
+        if self.in_use.swap(true, Relaxed) {
+            panic!("Can't send more than one message!");
+        }
+        // Start from zero so any bytes of the usize that T doesn't cover are
+        // still initialized
+        let mut bits = 0usize;
+        // Safety: T fits in a usize with compatible alignment, checked in new
+        unsafe { ptr::write(&mut bits as *mut usize as *mut T, message) };
+        // Only one send can get past in_use, so nothing races with this store,
+        // the Release store of ready below publishes it
+        self.message.store(bits, Relaxed);
+        self.ready.store(true, Release);
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.ready.load(Relaxed)
+    }
+
+    /// Panics if no message is available yet,
+    /// or if message was already consumed
+    pub fn receive(&self) -> T {
+        if !self.ready.swap(false, Acquire) {
+            panic!("No message available!");
+        }
+        let bits = self.message.load(Relaxed);
+        // Safety: bits holds a valid T, written by send, which we've seen
+        // through the Acquire swap of ready
+        unsafe { ptr::read(&bits as *const usize as *const T) }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use crate::channels::safer_oneshot;
+
+    // These also run under miri (cargo +nightly miri test inline_oneshot),
+    // which checks the bytes going in and out of the usize are initialized
+
+    #[test]
+    fn small_messages_round_trip() {
+        let c = Channel::<u8>::new();
+        thread::scope(|s| {
+            s.spawn(|| c.send(200));
+            while !c.is_ready() {
+                thread::yield_now();
+            }
+        });
+        assert_eq!(c.receive(), 200);
+        let c = Channel::<char>::new();
+        c.send('é');
+        assert_eq!(c.receive(), 'é');
+        let c = Channel::<i16>::new();
+        c.send(-2);
+        assert_eq!(c.receive(), -2);
+        let c = Channel::<bool>::new();
+        c.send(true);
+        assert!(c.receive());
+    }
+
+    // Too big for Inline, so it goes through safer_oneshot instead
+    #[test]
+    fn large_messages_use_safer_oneshot() {
+        #[derive(Debug, PartialEq)]
+        struct Large([u64; 32], String);
+        let c = safer_oneshot::Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| c.send(Large([7; 32], String::from("large"))));
+        });
+        assert_eq!(c.receive(), Large([7; 32], String::from("large")));
+    }
+
+    #[test]
+    fn receive_before_send_panics() {
+        let c = Channel::<u32>::new();
+        assert!(std::panic::catch_unwind(|| c.receive()).is_err());
+    }
+}
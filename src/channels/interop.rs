@@ -0,0 +1,87 @@
+use std::sync::mpsc;
+use std::thread;
+use std::thread::JoinHandle;
+use crate::channels::compile_time_oneshot;
+use crate::channels::wake::ParkStrategy;
+
+// Helpers for moving messages between this crate's channels and the standard
+// library's std::sync::mpsc channels.
+
+/// Spawns a thread that waits for the oneshot's message and sends it on into
+/// the std sender.
+///
+/// If the std receiver is gone by then, the message is dropped. If the oneshot's
+/// Sender is dropped without sending, the thread stops without sending anything,
+/// and the std sender is dropped, so the std receiver sees it disconnect.
+pub fn forward_to_std<T>(receiver: compile_time_oneshot::Receiver<T>, sender: mpsc::Sender<T>) -> JoinHandle<()>
+where
+    T: Send + 'static,
+{
+    thread::spawn(move || {
+        // Parked until the oneshot's Sender sends, or is dropped
+        if let Ok(message) = receiver.receive_with(ParkStrategy) {
+            // Ignore the error, all it tells us is that there's no one to
+            // receive it
+            let _ = sender.send(message);
+        }
+    })
+}
+
+/// Spawns a thread that waits for the first message on the std receiver and
+/// sends it into a new oneshot, returning the oneshot's Receiver.
+///
+/// If all std senders are dropped without sending, the oneshot's Sender is
+/// dropped too, and its Receiver gets RecvError::SenderDropped.
+pub fn from_std_recv<T>(receiver: mpsc::Receiver<T>) -> compile_time_oneshot::Receiver<T>
+where
+    T: Send + 'static,
+{
+    let (sender, oneshot_receiver) = compile_time_oneshot::channel();
+    thread::spawn(move || {
+        if let Ok(message) = receiver.recv() {
+            sender.send(message);
+        }
+    });
+    oneshot_receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forwards_into_std() {
+        let (s, r) = compile_time_oneshot::channel();
+        let (std_sender, std_receiver) = mpsc::channel();
+        let forwarder = forward_to_std(r, std_sender);
+        s.send(5);
+        assert_eq!(std_receiver.recv(), Ok(5));
+        forwarder.join().unwrap();
+    }
+
+    #[test]
+    fn forwards_from_std() {
+        let (std_sender, std_receiver) = mpsc::channel();
+        let r = from_std_recv(std_receiver);
+        std_sender.send("x").unwrap();
+        assert_eq!(r.receive_with(ParkStrategy), Ok("x"));
+    }
+
+    #[test]
+    fn forwarding_stops_when_the_oneshot_sender_is_dropped() {
+        let (s, r) = compile_time_oneshot::channel::<i32>();
+        let (std_sender, std_receiver) = mpsc::channel();
+        let forwarder = forward_to_std(r, std_sender);
+        drop(s);
+        forwarder.join().unwrap();
+        assert_eq!(std_receiver.recv(), Err(mpsc::RecvError));
+    }
+
+    #[test]
+    fn dropped_std_senders_drop_the_oneshot_sender() {
+        let (std_sender, std_receiver) = mpsc::channel::<i32>();
+        let r = from_std_recv(std_receiver);
+        drop(std_sender);
+        assert_eq!(r.receive_with(ParkStrategy), Err(compile_time_oneshot::RecvError::SenderDropped));
+    }
+}
@@ -0,0 +1,59 @@
+use std::iter;
+use std::time::Duration;
+use crate::channels::mutex_based;
+
+// Anything that can wait a limited time for a message, None meaning nothing
+// arrived in time (or nothing ever will)
+pub trait ReceiveTimeout<T> {
+    fn receive_timeout(&self, timeout: Duration) -> Option<T>;
+}
+
+impl<T> ReceiveTimeout<T> for mutex_based::Channel<T> {
+    fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        mutex_based::Channel::receive_timeout(self, timeout)
+    }
+}
+
+// So timeout_iter can borrow a channel instead of taking it
+impl<T, R: ReceiveTimeout<T> + ?Sized> ReceiveTimeout<T> for &R {
+    fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        (**self).receive_timeout(timeout)
+    }
+}
+
+// Turns a receiver into an iterator over its messages, where each next waits
+// up to timeout for the next message. The iteration ends the first time
+// nothing arrives in time, so a `for message in timeout_iter(&channel, d)`
+// loop ends by itself once the channel goes quiet.
+pub fn timeout_iter<T, R: ReceiveTimeout<T>>(receiver: R, timeout: Duration) -> impl Iterator<Item = T> {
+    // Once it has timed out, keep returning None, like a fused iterator
+    iter::from_fn(move || receiver.receive_timeout(timeout)).fuse()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Instant;
+
+    #[test]
+    fn ends_once_the_channel_goes_quiet() {
+        let c = mutex_based::Channel::new();
+        c.send(1);
+        c.send(2);
+        thread::scope(|s| {
+            s.spawn(|| {
+                // Well within the timeout, so the iteration keeps going
+                thread::sleep(Duration::from_millis(10));
+                c.send(3);
+            });
+            let start = Instant::now();
+            let received: Vec<_> = timeout_iter(&c, Duration::from_millis(200)).collect();
+            assert_eq!(received, [1, 2, 3]);
+            assert!(start.elapsed() >= Duration::from_millis(200));
+        });
+        // Anything sent afterwards is left for the next receive
+        c.send(4);
+        assert_eq!(c.receive(), 4);
+    }
+}
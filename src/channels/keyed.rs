@@ -0,0 +1,141 @@
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::sync::{Condvar, Mutex};
+
+// Like mutex_based::Channel, but every message is sent under a key, and a
+// receiver only takes messages for the key it asks for, topic/partition style.
+// Messages for one key come out in the order they were sent.
+//
+// Cons: There's only one condition variable for all keys, so every send has
+// to wake every waiting receiver, and the ones waiting on other keys go right
+// back to sleep. A condvar per key would avoid that, at the cost of managing
+// them as keys come and go.
+pub struct KeyedChannel<K, T> {
+    queues: Mutex<HashMap<K, VecDeque<T>>>,
+    item_ready: Condvar,
+}
+
+impl<K: Eq + Hash, T> Default for KeyedChannel<K, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Eq + Hash, T> KeyedChannel<K, T> {
+    pub fn new() -> Self {
+        Self {
+            queues: Mutex::new(HashMap::new()),
+            item_ready: Condvar::new(),
+        }
+    }
+
+    pub fn send(&self, key: K, message: T) {
+        self.queues.lock().unwrap().entry(key).or_default().push_back(message);
+        self.item_ready.notify_all();
+    }
+
+    // Blocks until there's a message for key. A key that nothing was ever sent
+    // under is no different from one whose messages were all received, we
+    // just wait for one to show up.
+    pub fn receive(&self, key: &K) -> T {
+        let mut b = self.queues.lock().unwrap();
+        loop {
+            if let Some(queue) = b.get_mut(key) {
+                if let Some(message) = queue.pop_front() {
+                    // Don't keep empty queues around for keys that aren't used
+                    // anymore
+                    if queue.is_empty() {
+                        b.remove(key);
+                    }
+                    return message;
+                }
+            }
+            b = self.item_ready.wait(b).unwrap();
+        }
+    }
+}
+
+// For counters keyed by id, where only the total per key matters
+impl<K: Eq + Hash> KeyedChannel<K, u64> {
+    // If there's already a message waiting for key, adds amount to it instead
+    // of queueing a new one, so a key that's incremented a lot doesn't fill
+    // up the queue. With several messages waiting, it's added to the last one,
+    // so nothing is counted before a message sent earlier than it.
+    // Wraps around on overflow, like CounterChannel.
+    pub fn send_or_increment(&self, key: K, amount: u64) {
+        let mut b = self.queues.lock().unwrap();
+        let queue = b.entry(key).or_default();
+        match queue.back_mut() {
+            Some(total) => {
+                *total = total.wrapping_add(amount);
+                // Nothing new to receive, no need to wake anyone
+            }
+            None => {
+                queue.push_back(amount);
+                drop(b);
+                self.item_ready.notify_all();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn each_key_gets_its_own_messages_in_order() {
+        let c = KeyedChannel::new();
+        c.send("a", 1);
+        c.send("b", 10);
+        c.send("a", 2);
+        c.send("b", 20);
+        assert_eq!(c.receive(&"b"), 10);
+        assert_eq!(c.receive(&"a"), 1);
+        assert_eq!(c.receive(&"a"), 2);
+        assert_eq!(c.receive(&"b"), 20);
+        assert!(c.queues.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn unknown_key_waits() {
+        let c = KeyedChannel::new();
+        thread::scope(|s| {
+            let late = s.spawn(|| c.receive(&"late"));
+            thread::sleep(std::time::Duration::from_millis(10));
+            // Wakes the receiver, but it's not its key
+            c.send("other", 1);
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert!(!late.is_finished());
+            c.send("late", 9);
+            assert_eq!(late.join().unwrap(), 9);
+        });
+        assert_eq!(c.receive(&"other"), 1);
+    }
+
+    #[test]
+    fn increments_collapse_into_one_message() {
+        let c = KeyedChannel::new();
+        c.send_or_increment("a", 1);
+        c.send_or_increment("b", 5);
+        c.send_or_increment("a", 2);
+        c.send_or_increment("a", 3);
+        assert_eq!(c.queues.lock().unwrap()[&"a"].len(), 1);
+        assert_eq!(c.receive(&"a"), 6);
+        assert_eq!(c.receive(&"b"), 5);
+        // Once received, the next increment starts a new total
+        c.send_or_increment("a", 4);
+        assert_eq!(c.receive(&"a"), 4);
+    }
+
+    #[test]
+    fn increment_adds_to_the_last_message() {
+        let c = KeyedChannel::new();
+        c.send("a", 1);
+        c.send("a", 2);
+        c.send_or_increment("a", 10);
+        assert_eq!(c.receive(&"a"), 1);
+        assert_eq!(c.receive(&"a"), 12);
+    }
+}
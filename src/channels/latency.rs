@@ -0,0 +1,180 @@
+// Records how long sends on a bounded channel spend waiting for space, as a
+// coarse histogram, to see how hard the backpressure is hitting senders.
+//
+// Also keeps track of how long receivers spend blocked waiting for a
+// message, as a total and a count, to see how often and how long consumers
+// starve.
+//
+// Only does anything with the send-latency (or wait-stats) feature enabled.
+// Without it, SendLatency (or WaitStats) is an empty struct and timed just
+// runs the closure, like Watch in debug.rs, so channels can use them
+// unconditionally.
+
+use std::time::Duration;
+#[cfg(any(feature = "send-latency", feature = "wait-stats"))]
+use std::sync::atomic::AtomicU64;
+#[cfg(any(feature = "send-latency", feature = "wait-stats"))]
+use std::sync::atomic::Ordering::Relaxed;
+#[cfg(any(feature = "send-latency", feature = "wait-stats"))]
+use std::time::Instant;
+
+// Upper bounds of the buckets, each bucket counts the sends that waited less
+// than its bound (and at least the previous one). The last bucket, past the
+// end of this, counts everything longer.
+pub const BUCKET_BOUNDS: [Duration; 6] = [
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+];
+pub const BUCKETS: usize = BUCKET_BOUNDS.len() + 1;
+
+pub(crate) struct SendLatency {
+    // Just counters, nothing is synchronized through them, so Relaxed
+    #[cfg(feature = "send-latency")]
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl SendLatency {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(feature = "send-latency")]
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    // Runs wait, and records how long it took
+    #[inline]
+    pub(crate) fn timed<R>(&self, wait: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "send-latency")]
+        {
+            let start = Instant::now();
+            let result = wait();
+            self.record(start.elapsed());
+            result
+        }
+        #[cfg(not(feature = "send-latency"))]
+        wait()
+    }
+
+    #[cfg(feature = "send-latency")]
+    fn record(&self, waited: Duration) {
+        let bucket = BUCKET_BOUNDS.iter().position(|&bound| waited < bound).unwrap_or(BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Relaxed);
+    }
+
+    #[cfg(feature = "send-latency")]
+    pub(crate) fn histogram(&self) -> [u64; BUCKETS] {
+        std::array::from_fn(|i| self.buckets[i].load(Relaxed))
+    }
+}
+
+pub(crate) struct WaitStats {
+    // Only statistics, so Relaxed. The two aren't updated together, an
+    // average computed while a wait is being recorded can be slightly off.
+    #[cfg(feature = "wait-stats")]
+    total_nanos: AtomicU64,
+    #[cfg(feature = "wait-stats")]
+    waits: AtomicU64,
+}
+
+impl WaitStats {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(feature = "wait-stats")]
+            total_nanos: AtomicU64::new(0),
+            #[cfg(feature = "wait-stats")]
+            waits: AtomicU64::new(0),
+        }
+    }
+
+    // Runs wait, and adds how long it took to the total
+    #[inline]
+    pub(crate) fn timed<R>(&self, wait: impl FnOnce() -> R) -> R {
+        #[cfg(feature = "wait-stats")]
+        {
+            let start = Instant::now();
+            let result = wait();
+            self.total_nanos.fetch_add(start.elapsed().as_nanos() as u64, Relaxed);
+            self.waits.fetch_add(1, Relaxed);
+            result
+        }
+        #[cfg(not(feature = "wait-stats"))]
+        wait()
+    }
+
+    // 0 if nothing has waited yet
+    #[cfg(feature = "wait-stats")]
+    pub(crate) fn average_nanos(&self) -> u64 {
+        let waits = self.waits.load(Relaxed);
+        self.total_nanos.load(Relaxed).checked_div(waits).unwrap_or(0)
+    }
+}
+
+#[cfg(all(test, feature = "send-latency"))]
+mod tests {
+    use super::*;
+    use crate::channels::mutex_based::Channel;
+    use std::thread;
+
+    #[test]
+    fn waits_land_in_their_buckets() {
+        let latency = SendLatency::new();
+        for waited in [Duration::ZERO, Duration::from_micros(50), Duration::from_millis(20), Duration::from_secs(5)] {
+            latency.record(waited);
+        }
+        assert_eq!(latency.histogram(), [1, 1, 0, 0, 1, 0, 1]);
+    }
+
+    #[test]
+    fn blocked_sends_are_recorded() {
+        let c = Channel::with_config(1, Some(1));
+        c.send(0);
+        for wait in [Duration::from_millis(30), Duration::from_millis(300)] {
+            thread::scope(|s| {
+                s.spawn(|| {
+                    thread::sleep(wait);
+                    c.receive();
+                });
+                c.send(1);
+            });
+        }
+        let histogram = c.send_latency_histogram();
+        // 10ms to 100ms, and 100ms to 1s
+        assert_eq!((histogram[4], histogram[5]), (1, 1));
+        // The first send didn't wait at all
+        assert_eq!(histogram.iter().sum::<u64>(), 3);
+    }
+}
+
+#[cfg(all(test, feature = "wait-stats"))]
+mod wait_stats_tests {
+    use crate::channels::mutex_based::Channel;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn only_blocking_receives_count() {
+        let c = Channel::new();
+        assert_eq!(c.average_wait_nanos(), 0);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                // Immediate, not counted
+                c.send(0);
+                c.receive();
+                // Has to wait for about 20ms
+                s.spawn(|| {
+                    thread::sleep(Duration::from_millis(20));
+                    c.send(1);
+                });
+                c.receive();
+            }
+        });
+        // Counting the immediate ones too would halve this
+        let average = Duration::from_nanos(c.average_wait_nanos());
+        assert!(average >= Duration::from_millis(20), "{average:?}");
+        assert!(average < Duration::from_millis(500), "{average:?}");
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use crate::channels::safer_oneshot::{self, RecvError};
+use crate::channels::wake::ParkStrategy;
+
+// A value that's computed the first time it's needed, on a worker thread,
+// and then kept, for expensive computations that should only ever run once.
+// The first get starts the worker and waits for its result through a oneshot,
+// any get called meanwhile waits for that same result, and every get after
+// that returns the kept value right away.
+pub struct Lazy<T, F = fn() -> T> {
+    // Taken out by the one get that starts the computation
+    init: Mutex<Option<F>>,
+    value: OnceLock<T>,
+}
+
+impl<T, F> Lazy<T, F>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    pub const fn new(init: F) -> Self {
+        Self { init: Mutex::new(Some(init)), value: OnceLock::new() }
+    }
+
+    /// Panics if the initializer panicked, on this and every later call, as
+    /// there's no value and no initializer left to try again with
+    pub fn get(&self) -> &T {
+        // OnceLock makes sure only one caller runs this, and everyone else
+        // waits for it
+        self.value.get_or_init(|| {
+            let init = self.init.lock().unwrap().take().expect("Lazy initializer panicked!");
+            let channel = safer_oneshot::Channel::new();
+            thread::scope(|s| {
+                s.spawn(|| {
+                    // If init panics, tell the receiver no value is coming,
+                    // instead of leaving it waiting forever
+                    struct CloseOnDrop<'a, T>(&'a safer_oneshot::Channel<T>);
+                    impl<T> Drop for CloseOnDrop<'_, T> {
+                        fn drop(&mut self) {
+                            // Does nothing if the value was sent
+                            self.0.close();
+                        }
+                    }
+                    let _close = CloseOnDrop(&channel);
+                    channel.send(init());
+                });
+                match channel.receive_with(ParkStrategy) {
+                    Ok(value) => value,
+                    Err(RecvError::Closed | RecvError::Empty) => panic!("Lazy initializer panicked!"),
+                }
+            })
+        })
+    }
+
+    // The value, if it's been computed yet, without starting or waiting for
+    // the computation
+    pub fn try_get(&self) -> Option<&T> {
+        self.value.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+    use std::time::Duration;
+
+    #[test]
+    fn initializer_runs_once_for_everyone() {
+        let runs = AtomicUsize::new(0);
+        let lazy = Lazy::new(|| {
+            runs.fetch_add(1, Relaxed);
+            thread::sleep(Duration::from_millis(20));
+            String::from("computed")
+        });
+        assert_eq!(lazy.try_get(), None);
+        thread::scope(|s| {
+            let handles: Vec<_> = (0..8).map(|_| s.spawn(|| lazy.get())).collect();
+            let first = lazy.get();
+            for h in handles {
+                // The very same value, not just an equal one
+                assert!(std::ptr::eq(h.join().unwrap(), first));
+            }
+        });
+        assert_eq!(runs.load(Relaxed), 1);
+        assert_eq!(lazy.try_get().map(String::as_str), Some("computed"));
+    }
+
+    #[test]
+    fn panicking_initializer_panics_every_get() {
+        let lazy: Lazy<u32, _> = Lazy::new(|| panic!("init failed"));
+        for _ in 0..2 {
+            assert!(panic::catch_unwind(AssertUnwindSafe(|| lazy.get())).is_err());
+        }
+        assert_eq!(lazy.try_get(), None);
+    }
+}
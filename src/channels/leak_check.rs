@@ -0,0 +1,253 @@
+// Counts messages that are sitting in a channel, to catch channels that lose
+// track of a message (never receiving it and never dropping it).
+// Every oneshot keeps a Tracker next to its message slot, and calls
+// message_sent when a message goes in, and message_gone when it comes out
+// again, either through a receive or through the channel's Drop (or a reset).
+//
+// Only does anything in the crate's own tests, or with the leak-check feature
+// enabled, otherwise the Tracker is an empty struct and these are empty
+// functions the compiler removes, so channels can call them unconditionally
+// without paying for anything per message.
+//
+// A message is counted against the LeakGuard of the thread that sends it, if
+// that thread has one, and the Tracker remembers which guard that was, so the
+// count still goes down when the message is received or dropped on another
+// thread. That way, tests running in parallel don't see each other's messages,
+// as long as each one only sends from threads it made a guard on.
+
+#[cfg(any(test, feature = "leak-check"))]
+mod enabled {
+    use std::cell::RefCell;
+    use std::marker::PhantomData;
+    use std::ptr;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicIsize, AtomicPtr, Ordering::Relaxed};
+
+    thread_local! {
+        // The live message count of the innermost LeakGuard on this thread
+        static SCOPE: RefCell<Option<Arc<AtomicIsize>>> = const { RefCell::new(None) };
+    }
+
+    // Points to the count (from Arc::into_raw) the message in the slot is
+    // counted against, or is null if there's no message, or the sending thread
+    // had no guard. Whoever swaps it out owns that reference.
+    // Relaxed is enough throughout: message_sent is called before the channel
+    // publishes the message and message_gone after the channel claims it, so
+    // the channel itself makes the pointer visible to whoever calls
+    // message_gone.
+    pub(crate) struct Tracker {
+        scope: AtomicPtr<AtomicIsize>,
+    }
+
+    impl Tracker {
+        pub(crate) const fn new() -> Self {
+            Self { scope: AtomicPtr::new(ptr::null_mut()) }
+        }
+
+        pub(crate) fn message_sent(&self) {
+            let Some(scope) = SCOPE.with(|s| s.borrow().clone()) else {
+                return;
+            };
+            scope.fetch_add(1, Relaxed);
+            self.set(Arc::into_raw(scope).cast_mut());
+        }
+
+        pub(crate) fn message_gone(&self) {
+            let scope = self.scope.swap(ptr::null_mut(), Relaxed);
+            if !scope.is_null() {
+                // Safety: We swapped it out, so we own this reference
+                let scope = unsafe { Arc::from_raw(scope) };
+                scope.fetch_sub(1, Relaxed);
+            }
+        }
+
+        // For moving the message itself to another channel's slot, so it stays
+        // counted against the same guard. Only transfer::relay does that, which
+        // needs std.
+        #[cfg(feature = "std")]
+        pub(crate) fn hand_over(&self, to: &Tracker) {
+            to.set(self.scope.swap(ptr::null_mut(), Relaxed));
+        }
+
+        fn set(&self, scope: *mut AtomicIsize) {
+            let old = self.scope.swap(scope, Relaxed);
+            if !old.is_null() {
+                // Only if the channel forgot to call message_gone, so it's
+                // counted as leaked, but the Arc itself isn't
+                drop(unsafe { Arc::from_raw(old) });
+            }
+        }
+    }
+
+    impl Drop for Tracker {
+        fn drop(&mut self) {
+            let scope = *self.scope.get_mut();
+            if !scope.is_null() {
+                drop(unsafe { Arc::from_raw(scope) });
+            }
+        }
+    }
+
+    // Counts the messages sent on this thread while it exists, and panics on
+    // drop if some of them are still around and not in a channel that's still
+    // alive to drop them, as those were leaked.
+    // Guards can be nested, the inner one counts on its own until it's dropped.
+    // Not Send, as it has to be dropped on the thread it was made on.
+    pub struct LeakGuard {
+        live: Arc<AtomicIsize>,
+        outer: Option<Arc<AtomicIsize>>,
+        _not_send: PhantomData<*const ()>,
+    }
+
+    impl Default for LeakGuard {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl LeakGuard {
+        pub fn new() -> Self {
+            let live = Arc::new(AtomicIsize::new(0));
+            let outer = SCOPE.with(|s| s.replace(Some(live.clone())));
+            Self { live, outer, _not_send: PhantomData }
+        }
+
+        // How many of the messages sent under this guard are live
+        pub fn leaked(&self) -> isize {
+            self.live.load(Relaxed)
+        }
+
+        // Gives back the number of leaked messages instead of panicking, for
+        // testing the guard itself
+        pub fn finish(mut self) -> isize {
+            let leaked = self.leaked();
+            self.end();
+            std::mem::forget(self);
+            leaked
+        }
+
+        fn end(&mut self) {
+            let outer = self.outer.take();
+            SCOPE.with(|s| *s.borrow_mut() = outer);
+        }
+    }
+
+    impl Drop for LeakGuard {
+        fn drop(&mut self) {
+            self.end();
+            // Don't panic while panicking, that would abort
+            if !std::thread::panicking() {
+                let leaked = self.leaked();
+                assert!(leaked <= 0, "{leaked} message(s) leaked!");
+            }
+        }
+    }
+}
+
+#[cfg(any(test, feature = "leak-check"))]
+pub(crate) use enabled::Tracker;
+#[cfg(any(test, feature = "leak-check"))]
+pub use enabled::LeakGuard;
+
+#[cfg(not(any(test, feature = "leak-check")))]
+pub(crate) struct Tracker;
+
+#[cfg(not(any(test, feature = "leak-check")))]
+impl Tracker {
+    #[inline]
+    pub(crate) const fn new() -> Self {
+        Self
+    }
+
+    #[inline]
+    pub(crate) fn message_sent(&self) {}
+
+    #[inline]
+    pub(crate) fn message_gone(&self) {}
+
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn hand_over(&self, _to: &Tracker) {}
+}
+
+// They use the other oneshots, which need std
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::channels::{
+        borrowing_oneshot, compile_time_oneshot, generational_oneshot, safer_oneshot,
+        unsafe_oneshot,
+    };
+    use std::thread;
+
+    #[test]
+    fn reports_a_forgotten_message() {
+        let guard = LeakGuard::new();
+        let leaked = safer_oneshot::Channel::new();
+        leaked.send(1);
+        std::mem::forget(leaked);
+        let (s, r) = compile_time_oneshot::channel();
+        s.send(2);
+        assert_eq!(r.receive(), Ok(2));
+        assert_eq!(guard.finish(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "1 message(s) leaked!")]
+    fn panics_on_a_leak() {
+        let _guard = LeakGuard::new();
+        let leaked = unsafe_oneshot::Channel::new();
+        unsafe { leaked.send(String::from("lost")) };
+        std::mem::forget(leaked);
+    }
+
+    #[test]
+    fn received_and_dropped_messages_arent_leaks() {
+        let guard = LeakGuard::new();
+        let c = safer_oneshot::Channel::new();
+        c.send(1);
+        assert_eq!(c.receive(), 1);
+        drop(safer_oneshot::Channel::ready_with(2));
+        let u = unsafe_oneshot::Channel::new();
+        unsafe { u.send(3) };
+        drop(u);
+        let mut b = borrowing_oneshot::Channel::new();
+        let (s, _r) = b.split();
+        s.send(4);
+        b.reset();
+        let g = generational_oneshot::Channel::new();
+        g.sender().send(5).unwrap();
+        g.reset();
+        let (s, r) = compile_time_oneshot::channel();
+        s.send(6);
+        drop(r);
+        assert_eq!(guard.finish(), 0);
+    }
+
+    #[test]
+    fn counts_messages_received_on_another_thread() {
+        let guard = LeakGuard::new();
+        let (s, r) = compile_time_oneshot::channel();
+        s.send(1);
+        let c = safer_oneshot::Channel::new();
+        c.send(2);
+        thread::scope(|scope| {
+            scope.spawn(|| {
+                assert_eq!(r.receive(), Ok(1));
+                assert_eq!(c.receive(), 2);
+            });
+        });
+        assert_eq!(guard.finish(), 0);
+    }
+
+    #[test]
+    fn nested_guards_count_on_their_own() {
+        let outer = LeakGuard::new();
+        let first = safer_oneshot::Channel::new();
+        first.send(1);
+        std::mem::forget(first);
+        let inner = LeakGuard::new();
+        assert_eq!(inner.finish(), 0);
+        assert_eq!(outer.finish(), 1);
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering::Relaxed;
+use crate::locks::spin_lock::SpinLock;
+
+// A single slot that only ever holds the latest value: post replaces whatever
+// hasn't been taken yet. Keeps count of how many values were replaced before
+// anyone took them, so a producer can tell the consumer is falling behind.
+//
+// The slot is guarded by our SpinLock, as it's only ever held long enough to
+// swap a value in or out.
+pub struct Mailbox<T> {
+    slot: SpinLock<Option<T>>,
+    // Only a statistic, so Relaxed is enough
+    overwritten: AtomicU64,
+}
+
+impl<T> Default for Mailbox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Mailbox<T> {
+    pub const fn new() -> Self {
+        Self {
+            slot: SpinLock::new(None),
+            overwritten: AtomicU64::new(0),
+        }
+    }
+
+    // Never blocks (other than on the spin lock), an unread value is dropped
+    pub fn post(&self, value: T) {
+        let old = self.slot.lock().replace(value);
+        // Dropped here, after unlocking, so a slow Drop doesn't keep everyone
+        // else spinning
+        if old.is_some() {
+            self.overwritten.fetch_add(1, Relaxed);
+        }
+    }
+
+    pub fn take(&self) -> Option<T> {
+        self.slot.lock().take()
+    }
+
+    // How many posted values were replaced before being taken, in total
+    pub fn overwritten_count(&self) -> u64 {
+        self.overwritten.load(Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_gets_the_latest_and_counts_the_rest() {
+        let mailbox = Mailbox::new();
+        assert_eq!(mailbox.take(), None);
+        for i in 1..=3 {
+            mailbox.post(i);
+        }
+        assert_eq!(mailbox.take(), Some(3));
+        assert_eq!(mailbox.take(), None);
+        assert_eq!(mailbox.overwritten_count(), 2);
+        // A taken value wasn't overwritten
+        mailbox.post(4);
+        assert_eq!(mailbox.overwritten_count(), 2);
+    }
+}
@@ -1,5 +1,65 @@
-pub mod mutex_based;
+// The modules built without the std feature, as they need nothing but core
+// (safer_oneshot only keeps part of itself, see there)
 pub mod unsafe_oneshot;
 pub mod safer_oneshot;
-pub(crate) mod compile_time_oneshot;
-mod borrowing_oneshot;
\ No newline at end of file
+pub mod leak_check;
+
+// Everything else needs std, declared through this so every one of them
+// doesn't need its own cfg
+macro_rules! std_modules {
+    ($($name:ident)*) => {
+        $(#[cfg(feature = "std")] pub mod $name;)*
+    };
+}
+
+std_modules! {
+    mutex_based
+    compile_time_oneshot
+    borrowing_oneshot
+    atomic_ptr_latest
+    transfer
+    mpmc_ring
+    interop
+    wake
+    inline_oneshot
+    generational_oneshot
+    counter
+    keyed
+    envelope
+    scatter
+    select
+    await_all
+    debug
+    broadcast
+    oneshot_pool
+    credit
+    mailbox
+    iter
+    wakeup_registry
+    sized
+    latency
+    bridge
+    coupled
+    ring_buffer
+    rendezvous
+    lazy
+    backpressure
+    sum_channel
+    dlq
+    filter
+    collect
+    ack
+    bench
+    spsc_ring
+    oneshot_queue
+    priority_queue
+    any_channel
+    timing
+    steal
+    mpsc_lockfree
+    poll
+    group
+}
+
+#[cfg(all(test, feature = "std"))]
+mod test_util;
@@ -0,0 +1,294 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+// Dmitry Vyukov's bounded MPMC queue.
+// A fixed array of slots, where every slot has its own sequence number telling
+// whether it's ready to be written to or read from for a given position.
+// enqueue_pos and dequeue_pos only ever count up, the slot for a position is
+// at position % capacity.
+//
+// For a slot at index i, with position pos (where pos % capacity == i):
+// seq == pos: The slot is empty and a sender can claim it for pos
+// seq == pos + 1: The slot holds the message for pos, a receiver can claim it
+// seq == pos + capacity: The message was taken, the slot is empty again for
+// the next lap around the ring
+//
+// Pros: Any number of senders and receivers, without locks, and with no memory
+// allocation after creation
+// Cons:
+// 1. Only non-blocking operations, it's up to the user to wait
+// 2. A sender or receiver that gets descheduled between claiming a position and
+// updating the slot's sequence blocks the others from getting past that slot
+// 3. Messages are only FIFO per position, two senders racing might get their
+// messages received in either order
+//
+// Panics if capacity is less than 2. With 1, pos + 1 and pos + capacity are
+// the same, so a slot holding a message would look empty for the next lap.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity >= 2, "Capacity must be at least 2!");
+    let slots = (0..capacity)
+        .map(|i| Slot {
+            seq: AtomicUsize::new(i),
+            message: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    let a = Arc::new(Channel {
+        slots,
+        enqueue_pos: AtomicUsize::new(0),
+        dequeue_pos: AtomicUsize::new(0),
+    });
+    (Sender { channel: a.clone() }, Receiver { channel: a })
+}
+
+pub struct Sender<T> {
+    channel: Arc<Channel<T>>,
+}
+
+pub struct Receiver<T> {
+    channel: Arc<Channel<T>>,
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self { channel: self.channel.clone() }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self { channel: self.channel.clone() }
+    }
+}
+
+struct Slot<T> {
+    seq: AtomicUsize,
+    message: UnsafeCell<MaybeUninit<T>>,
+}
+
+struct Channel<T> {
+    slots: Box<[Slot<T>]>,
+    enqueue_pos: AtomicUsize,
+    dequeue_pos: AtomicUsize,
+}
+
+// Messages only ever go from one thread to another, never shared, so T being
+// Send is enough
+unsafe impl<T> Sync for Channel<T> where T: Send {}
+
+// Everything from dequeue_pos up to enqueue_pos is a message that was sent
+// but never received. We have exclusive access here, so we don't have to
+// look at the sequence numbers.
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        let end = *self.enqueue_pos.get_mut();
+        let mut pos = *self.dequeue_pos.get_mut();
+        while pos != end {
+            let slot = &mut self.slots[pos % self.slots.len()];
+            unsafe { slot.message.get_mut().assume_init_drop() }
+            pos = pos.wrapping_add(1);
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Gives the message back if the channel is full
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        let c = &*self.channel;
+        let mut pos = c.enqueue_pos.load(Relaxed);
+        loop {
+            let slot = &c.slots[pos % c.slots.len()];
+            // Acquire, so the receiver that emptied this slot on the previous
+            // lap is done reading it before we write to it
+            let seq = slot.seq.load(Acquire);
+            let diff = seq.wrapping_sub(pos) as isize;
+            if diff == 0 {
+                // The slot is free for pos, try to claim pos for ourselves
+                match c.enqueue_pos.compare_exchange_weak(pos, pos.wrapping_add(1), Relaxed, Relaxed) {
+                    Ok(_) => {
+                        // Safety: We're the only one that claimed pos, and the slot
+                        // is empty, no one else touches it until we bump seq
+                        unsafe { (*slot.message.get()).write(message); }
+                        // Release the message to the receiver that claims pos
+                        slot.seq.store(pos.wrapping_add(1), Release);
+                        return Ok(());
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if diff < 0 {
+                // The slot still holds the message from the previous lap
+                return Err(message);
+            } else {
+                // Another sender claimed pos before us, try again with the newest
+                pos = c.enqueue_pos.load(Relaxed);
+            }
+        }
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Returns None if the channel is empty
+    pub fn try_recv(&self) -> Option<T> {
+        let c = &*self.channel;
+        let mut pos = c.dequeue_pos.load(Relaxed);
+        loop {
+            let slot = &c.slots[pos % c.slots.len()];
+            // Acquire, pairs with the Release store in try_send, so the message
+            // is fully written when we see the sequence number
+            let seq = slot.seq.load(Acquire);
+            let diff = seq.wrapping_sub(pos.wrapping_add(1)) as isize;
+            if diff == 0 {
+                match c.dequeue_pos.compare_exchange_weak(pos, pos.wrapping_add(1), Relaxed, Relaxed) {
+                    Ok(_) => {
+                        // Safety: We're the only one that claimed pos, and the slot
+                        // holds the message for pos
+                        let message = unsafe { (*slot.message.get()).assume_init_read() };
+                        // Mark the slot as empty for the next lap
+                        slot.seq.store(pos.wrapping_add(c.slots.len()), Release);
+                        return Some(message);
+                    }
+                    Err(p) => pos = p,
+                }
+            } else if diff < 0 {
+                // Nothing sent for pos yet
+                return None;
+            } else {
+                pos = c.dequeue_pos.load(Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::thread;
+
+    #[test]
+    fn full_and_empty() {
+        let (tx, rx) = channel(3);
+        assert_eq!(rx.try_recv(), None);
+        for i in 0..3 {
+            tx.try_send(i).unwrap();
+        }
+        assert_eq!(tx.try_send(9), Err(9));
+        for i in 0..3 {
+            assert_eq!(rx.try_recv(), Some(i));
+        }
+        assert_eq!(rx.try_recv(), None);
+        // Around the ring a few more times
+        for i in 0..10 {
+            tx.try_send(i).unwrap();
+            assert_eq!(rx.try_recv(), Some(i));
+        }
+    }
+
+    #[test]
+    fn no_lost_or_duplicated_messages() {
+        const PRODUCERS: usize = 4;
+        const PER_PRODUCER: usize = 5000;
+        let (tx, rx) = channel(16);
+        let seen: Vec<AtomicUsize> = (0..PRODUCERS * PER_PRODUCER).map(|_| AtomicUsize::new(0)).collect();
+        let received = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for p in 0..PRODUCERS {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..PER_PRODUCER {
+                        let mut m = p * PER_PRODUCER + i;
+                        while let Err(back) = tx.try_send(m) {
+                            m = back;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            for _ in 0..4 {
+                let rx = rx.clone();
+                let (seen, received) = (&seen, &received);
+                s.spawn(move || {
+                    // Every message from one producer that this receiver gets
+                    // has to come in the order it was sent
+                    let mut last = [None; PRODUCERS];
+                    while received.load(Relaxed) < PRODUCERS * PER_PRODUCER {
+                        let Some(m) = rx.try_recv() else {
+                            thread::yield_now();
+                            continue;
+                        };
+                        let (p, i) = (m / PER_PRODUCER, m % PER_PRODUCER);
+                        assert!(last[p] < Some(i), "producer {p}'s {i} after {:?}", last[p]);
+                        last[p] = Some(i);
+                        seen[m].fetch_add(1, Relaxed);
+                        received.fetch_add(1, Relaxed);
+                    }
+                });
+            }
+        });
+        assert!(seen.iter().all(|n| n.load(Relaxed) == 1));
+        assert_eq!(rx.try_recv(), None);
+    }
+
+    #[test]
+    fn single_receiver_gets_each_senders_messages_in_order() {
+        let (tx, rx) = channel(4);
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            for p in 0..3 {
+                let tx = tx.clone();
+                s.spawn(move || {
+                    for i in 0..2000 {
+                        let mut m = (p, i);
+                        while let Err(back) = tx.try_send(m) {
+                            m = back;
+                            thread::yield_now();
+                        }
+                    }
+                });
+            }
+            s.spawn(|| {
+                let mut next = [0; 3];
+                while next.iter().sum::<i32>() < 6000 {
+                    match rx.try_recv() {
+                        Some((p, i)) => {
+                            assert_eq!(i, next[p]);
+                            next[p] += 1;
+                        }
+                        None => thread::yield_now(),
+                    }
+                }
+                done.store(true, Relaxed);
+            });
+        });
+        assert!(done.load(Relaxed));
+    }
+
+    #[test]
+    fn drops_unreceived_messages() {
+        let a = Arc::new(());
+        let (tx, rx) = channel(4);
+        for _ in 0..3 {
+            tx.try_send(a.clone()).unwrap();
+        }
+        drop(rx.try_recv());
+        drop((tx, rx));
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn handles_clone_without_t_clone() {
+        struct NotClone;
+        let (tx, rx) = channel::<NotClone>(2);
+        let _ = (tx.clone(), rx.clone());
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 2!")]
+    fn capacity_one_panics() {
+        // Would take a second message on top of the first, and leak it
+        channel::<String>(1);
+    }
+}
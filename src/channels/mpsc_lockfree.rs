@@ -0,0 +1,192 @@
+use std::cell::{Cell, UnsafeCell};
+use std::marker::PhantomData;
+use std::ptr;
+use std::sync::Arc;
+use std::sync::atomic::AtomicPtr;
+use std::sync::atomic::Ordering::{AcqRel, Acquire, Release};
+
+// Any number of senders, one receiver, without locks: the queue is a linked
+// list of Box allocated nodes. Senders never block, and never wait for each
+// other, a send is one allocation, one swap and one store.
+//
+// The list always starts with a node whose message has already been taken
+// (or a stub, at the start), head points at it, and only the receiver
+// touches head. tail points at the last node, the one sends link behind.
+// A send swaps its node in as the new tail, which only one swap can do for
+// any given old tail, and then links the old tail to it, so every node ends
+// up linked exactly once, in the order the swaps happened.
+//
+// Cons: In between a send's swap and its link, the list is cut in two, so
+// try_recv sees nothing past the old tail, even messages sent after it,
+// until that send finishes. A message is never lost, just late.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let stub = Box::into_raw(Box::new(Node { next: AtomicPtr::new(ptr::null_mut()), message: None }));
+    let shared = Arc::new(Shared { head: UnsafeCell::new(stub), tail: AtomicPtr::new(stub) });
+    (Sender { shared: shared.clone() }, Receiver { shared, _not_sync: PhantomData })
+}
+
+struct Node<T> {
+    next: AtomicPtr<Node<T>>,
+    // None for the node head points at, its message was already taken
+    message: Option<T>,
+}
+
+struct Shared<T> {
+    // Only the receiver touches this
+    head: UnsafeCell<*mut Node<T>>,
+    tail: AtomicPtr<Node<T>>,
+}
+
+// Messages go from the senders' threads to the receiver's, and only the
+// Receiver, which isn't Sync, touches head
+unsafe impl<T> Send for Shared<T> where T: Send {}
+unsafe impl<T> Sync for Shared<T> where T: Send {}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// Not Clone, and the Cell makes it !Sync, so there's only ever one thread
+// receiving
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    _not_sync: PhantomData<Cell<()>>,
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+// Only once every Sender and the Receiver are gone, as a send links onto the
+// tail node, which has to stay alive for it. Everything from head on is
+// still in the list, including the messages never received.
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let mut node = *self.head.get_mut();
+        while !node.is_null() {
+            // Safety: Every node in the list came from Box::into_raw, and
+            // we have exclusive access to all of them now
+            let mut boxed = unsafe { Box::from_raw(node) };
+            node = *boxed.next.get_mut();
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    // Never blocks
+    pub fn send(&self, message: T) {
+        let node = Box::into_raw(Box::new(Node { next: AtomicPtr::new(ptr::null_mut()), message: Some(message) }));
+        // Release, so the sender that links onto our node (after swapping it
+        // out as the tail) sees it initialized. Acquire, for the same reason
+        // with the node we get, which another sender made.
+        let prev = self.shared.tail.swap(node, AcqRel);
+        // Release the message to the receiver, which loads next with Acquire.
+        // Safety: prev stays alive until we've linked it, the receiver never
+        // frees a node it can't get past, and it can't get past prev while
+        // prev's next is null
+        unsafe { (*prev).next.store(node, Release) };
+    }
+}
+
+impl<T> Receiver<T> {
+    // None if there's nothing to receive, or if the next message's send is
+    // still in the middle of linking it (see the cons above)
+    pub fn try_recv(&self) -> Option<T> {
+        // Safety: Only this Receiver touches head, and it isn't Sync
+        let head = unsafe { &mut *self.shared.head.get() };
+        // Acquire pairs with the Release store of next in send
+        let next = unsafe { (**head).next.load(Acquire) };
+        if next.is_null() {
+            return None;
+        }
+        let old = std::mem::replace(head, next);
+        // Safety: No sender touches old anymore, its next is already set,
+        // and next stays alive as the new head. Taking the message out of
+        // next makes it the empty node at the front.
+        unsafe {
+            drop(Box::from_raw(old));
+            (*next).message.take()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn many_senders_sum_to_the_total() {
+        const SENDERS: usize = 4;
+        const PER_SENDER: usize = if cfg!(miri) { 100 } else { 50_000 };
+        let (s, r) = channel();
+        thread::scope(|scope| {
+            for _ in 0..SENDERS {
+                let s = s.clone();
+                scope.spawn(move || {
+                    for i in 1..=PER_SENDER {
+                        s.send(i);
+                    }
+                });
+            }
+            let mut sum = 0;
+            let mut received = 0;
+            while received < SENDERS * PER_SENDER {
+                match r.try_recv() {
+                    Some(i) => {
+                        sum += i;
+                        received += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+            assert_eq!(sum, SENDERS * PER_SENDER * (PER_SENDER + 1) / 2);
+        });
+        assert_eq!(r.try_recv(), None);
+    }
+
+    #[test]
+    fn each_senders_messages_arrive_in_order() {
+        const PER_SENDER: usize = if cfg!(miri) { 50 } else { 10_000 };
+        let (s, r) = channel();
+        thread::scope(|scope| {
+            for id in 0..2 {
+                let s = s.clone();
+                scope.spawn(move || {
+                    for i in 0..PER_SENDER {
+                        s.send((id, i));
+                    }
+                });
+            }
+            let mut next = [0; 2];
+            while next != [PER_SENDER; 2] {
+                match r.try_recv() {
+                    Some((id, i)) => {
+                        assert_eq!(i, next[id]);
+                        next[id] += 1;
+                    }
+                    None => thread::yield_now(),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn drops_messages_never_received() {
+        let message = Arc::new(());
+        let (s, r) = channel();
+        for _ in 0..3 {
+            s.send(message.clone());
+        }
+        drop(r.try_recv());
+        // The Receiver going first doesn't free anything, the Sender still
+        // links onto the tail
+        drop(r);
+        assert_eq!(Arc::strong_count(&message), 3);
+        drop(s);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+}
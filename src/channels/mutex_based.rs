@@ -1,5 +1,13 @@
 use std::collections::VecDeque;
-use std::sync::{Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::Thread;
+use std::time::{Duration, Instant};
+use crate::channels::debug::{NotifySeq, Watch};
+use crate::channels::latency::{SendLatency, WaitStats};
+use crate::channels::wake;
+use crate::locks::spin_lock::SpinLockGuard;
 
 // Notes:
 // Didn't have to use atomics, because all types that compose Channel are
@@ -7,6 +15,71 @@ use std::sync::{Condvar, Mutex};
 pub struct Channel<T> {
     queue: Mutex<VecDeque<T>>,
     item_ready: Condvar,
+    // Only used when the channel has a max capacity, see with_config.
+    max_capacity: Option<usize>,
+    space_available: Condvar,
+    // Only used by fair channels, see new_fair.
+    // These are only ever touched while holding the queue lock, so Relaxed is
+    // enough, the mutex does the synchronizing for us.
+    fair: bool,
+    next_ticket: AtomicUsize,
+    now_serving: AtomicUsize,
+    // How many receive_min_batch calls are waiting. Only touched while
+    // holding the queue lock, like the tickets.
+    batch_waiters: AtomicUsize,
+    // Slots held by Reservations, which count as taken for senders, see
+    // reserve. Only touched while holding the queue lock.
+    reserved: AtomicUsize,
+    // Set when the last Sender from channel() is dropped, or by close. Only
+    // touched while holding the queue lock.
+    disconnected: AtomicBool,
+    // Lets the deadlock detector see how long receivers have been waiting
+    watch: Watch,
+    // How long sends waited for space, only recorded with a max capacity
+    send_latency: SendLatency,
+    // Checks every queued message was notified about, in debug builds
+    notify_seq: NotifySeq,
+    // How long receivers had to wait, when they had to
+    wait_stats: WaitStats,
+    // Threads waiting on more than one channel, which can't wait on our
+    // item_ready, get unparked on every send instead, see collect.
+    // observer_count is only changed while holding the observers lock, and
+    // read after a send unlocks the queue, see add_observer.
+    observers: Mutex<Vec<Thread>>,
+    observer_count: AtomicUsize,
+}
+
+// Messages that were never received are dropped front to back, in the order
+// they were sent. Each one is popped before it's dropped, with a guard armed
+// around its drop: if dropping it panics, the guard drops the rest of the
+// queue while unwinding, so none of them are leaked, and the one that
+// panicked is already out of the queue, so it isn't dropped twice. (A second
+// panic while unwinding aborts, as it would anywhere else.)
+// No locking needed, we have exclusive access. A poisoned mutex still holds
+// the messages, so we drop those too.
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        struct DropRest<'a, T>(&'a mut VecDeque<T>);
+        impl<T> Drop for DropRest<'_, T> {
+            fn drop(&mut self) {
+                while let Some(message) = self.0.pop_front() {
+                    drop(message);
+                }
+            }
+        }
+        let queue = self.queue.get_mut().unwrap_or_else(|e| e.into_inner());
+        while let Some(message) = queue.pop_front() {
+            let guard = DropRest(queue);
+            drop(message);
+            std::mem::forget(guard);
+        }
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 // Pros: This is very flexible, allowing any number of sending and receiving threads.
@@ -16,31 +89,1493 @@ pub struct Channel<T> {
 // to wait for that thread to finish
 // 3. The queue could grow without bounds
 impl<T> Channel<T> {
+    // Not const like the oneshots' new, even though Mutex, Condvar and
+    // VecDeque all have const new: with the deadlock-detector feature, Watch
+    // registers the channel in the detector's registry, which allocates.
     pub fn new() -> Self {
+        Self::with_options(VecDeque::new(), None, false)
+    }
+
+    // A fair channel serves waiting receivers in the order they called receive.
+    // With a plain channel, which receiver gets woken by notify_one is up to the
+    // OS, so a receiver can starve while others keep getting messages.
+    // Each receiver takes a ticket when it calls receive, and only the receiver
+    // whose ticket is being served may take a message, like the queue at a deli
+    // counter.
+    // Cons: Every send and receive has to wake all waiting receivers, since only
+    // one of them is the right one
+    // Only receive, receive_pair and the blocking receive of channel()'s
+    // Receiver (so also iterating it) wait their turn. Everything else that
+    // takes messages, the try, timeout and batch receives, receive_try_process
+    // and the drains, takes them right away, ahead of whoever is waiting in
+    // line, and so does anything built on those (Pollable or ChannelGroup, say).
+    // A receive that can give up can't hold a ticket, as a ticket that's never
+    // served leaves everyone behind it waiting forever.
+    pub fn new_fair() -> Self {
+        Self::with_options(VecDeque::new(), None, true)
+    }
+
+    // Preallocates room for initial_capacity messages, so the first sends don't
+    // have to grow the queue (see con 2 above).
+    // With a max_capacity, send blocks while the queue is full, until a receiver
+    // makes room, which fixes con 3. The queue never grows past max_capacity.
+    // Panics if max_capacity is Some(0), as nothing could ever be sent.
+    pub fn with_config(initial_capacity: usize, max_capacity: Option<usize>) -> Self {
+        assert!(max_capacity != Some(0), "Max capacity must be at least 1!");
+        Self::with_options(VecDeque::with_capacity(initial_capacity), max_capacity, false)
+    }
+
+    // All the constructors end up here. Struct update syntax (..Self::new())
+    // doesn't work for us, as Channel implements Drop.
+    fn with_options(queue: VecDeque<T>, max_capacity: Option<usize>, fair: bool) -> Self {
         Self {
-            queue: Mutex::new(VecDeque::new()),
+            queue: Mutex::new(queue),
             item_ready: Condvar::new(),
+            max_capacity,
+            space_available: Condvar::new(),
+            fair,
+            next_ticket: AtomicUsize::new(0),
+            now_serving: AtomicUsize::new(0),
+            batch_waiters: AtomicUsize::new(0),
+            reserved: AtomicUsize::new(0),
+            disconnected: AtomicBool::new(false),
+            watch: Watch::new(std::any::type_name::<Self>()),
+            send_latency: SendLatency::new(),
+            notify_seq: NotifySeq::new(),
+            wait_stats: WaitStats::new(),
+            observers: Mutex::new(Vec::new()),
+            observer_count: AtomicUsize::new(0),
         }
     }
 
     // Locks the mutex to push a new message onto the back of the queue.
     // Notifies one waiting receiver, after unlocking the queue.
+    // If the channel has a max capacity and is full, waits for a receiver to
+    // make room first.
     pub fn send(&self, message: T) {
-        self.queue.lock().unwrap().push_back(message);
+        let mut b = self.queue.lock().unwrap();
+        if let Some(max) = self.max_capacity {
+            b = self.send_latency.timed(|| {
+                // Loop in case of spurious wakeups, or another sender taking
+                // the space before us
+                while b.len() + self.reserved.load(Relaxed) >= max {
+                    b = self.space_available.wait(b).unwrap();
+                }
+                b
+            });
+        }
+        b.push_back(message);
+        drop(b);
         // For above, Guard out of scope here, so mutex is unlocked.
-        self.item_ready.notify_one();
+        self.notify_sent();
+    }
+
+    // Like send, but gives up waiting for space once timeout has passed, and
+    // gives the message back. Without a max capacity this never waits.
+    pub fn send_timeout(&self, message: T, timeout: Duration) -> Result<(), T> {
+        let deadline = Instant::now() + timeout;
+        let mut b = self.queue.lock().unwrap();
+        if let Some(max) = self.max_capacity {
+            b = match self.send_latency.timed(|| {
+                // Loop in case of spurious wakeups, or another sender taking
+                // the space before us, with whatever time is left
+                while b.len() + self.reserved.load(Relaxed) >= max {
+                    let left = deadline.checked_duration_since(Instant::now())?;
+                    b = self.space_available.wait_timeout(b, left).unwrap().0;
+                }
+                Some(b)
+            }) {
+                Some(b) => b,
+                None => return Err(message),
+            };
+        }
+        b.push_back(message);
+        drop(b);
+        self.notify_sent();
+        Ok(())
+    }
+
+    // Holds on to n slots for a batch of messages, so it can be sent all at
+    // once later without waiting, or not at all. Slots are only reserved if
+    // they're all free right now, otherwise this returns None without
+    // waiting, rather than holding on to some slots while waiting for the
+    // rest, which two senders doing that at once could deadlock on.
+    // Without a max capacity there's always room.
+    pub fn reserve(&self, n: usize) -> Option<Reservation<'_, T>> {
+        let b = self.queue.lock().unwrap();
+        let reserved = self.reserved.load(Relaxed);
+        if self.max_capacity.is_some_and(|max| b.len() + reserved + n > max) {
+            return None;
+        }
+        self.reserved.store(reserved + n, Relaxed);
+        Some(Reservation { channel: self, slots: n })
+    }
+
+    // How many sends waited how long for space, bucketed by
+    // latency::BUCKET_BOUNDS. Sends that didn't have to wait end up in the
+    // first bucket. Always all zeros without a max capacity.
+    #[cfg(feature = "send-latency")]
+    pub fn send_latency_histogram(&self) -> [u64; crate::channels::latency::BUCKETS] {
+        self.send_latency.histogram()
+    }
+
+    // Waits until at least min messages are queued, then takes up to max of
+    // them, in one locked operation, for receivers that work best on batches
+    // and don't want to be woken up for every single message.
+    // If senders stop before min is reached, this waits forever, see
+    // receive_min_batch_timeout.
+    // Panics if min is bigger than max, or than the max capacity, as there
+    // could never be enough messages.
+    pub fn receive_min_batch(&self, min: usize, max: usize) -> Vec<T> {
+        self.receive_batch(min, max, None)
+    }
+
+    // Same, but once timeout has passed, takes whatever is queued (up to max)
+    // instead of waiting any longer, even if that's fewer than min, or none.
+    pub fn receive_min_batch_timeout(&self, min: usize, max: usize, timeout: Duration) -> Vec<T> {
+        self.receive_batch(min, max, Some(Instant::now() + timeout))
+    }
+
+    fn receive_batch(&self, min: usize, max: usize, deadline: Option<Instant>) -> Vec<T> {
+        assert!(min <= max, "min can't be bigger than max!");
+        assert!(self.max_capacity.is_none_or(|cap| min <= cap), "min can't be bigger than the max capacity!");
+        let mut b = self.queue.lock().unwrap();
+        if b.len() < min {
+            // While we're waiting, senders wake everyone up, as a single
+            // wakeup might go to us while we still don't have enough, and
+            // never get to a receiver that would take the message
+            self.batch_waiters.fetch_add(1, Relaxed);
+            while b.len() < min {
+                match deadline {
+                    None => b = self.item_ready.wait(b).unwrap(),
+                    Some(deadline) => match deadline.checked_duration_since(Instant::now()) {
+                        Some(left) => b = self.item_ready.wait_timeout(b, left).unwrap().0,
+                        None => break,
+                    },
+                }
+            }
+            self.batch_waiters.fetch_sub(1, Relaxed);
+        }
+        let n = b.len().min(max);
+        let batch: Vec<T> = b.drain(..n).collect();
+        self.notify_seq.consumed(n);
+        drop(b);
+        self.notify_received(n);
+        batch
+    }
+
+    // Sends every message, in order, each one like send, so with a max
+    // capacity this blocks whenever the queue is full. Another sender's
+    // messages can end up in between ours.
+    pub fn send_all(&self, messages: impl IntoIterator<Item = T>) {
+        for message in messages {
+            self.send(message);
+        }
+    }
+
+    // For a receiver that couldn't process a message: puts it back at the
+    // front of the queue, so it's the next one received, ahead of everything
+    // sent in the meantime. Notifies one waiting receiver, like send.
+    // Never waits for space, the message was only just taken out. On a
+    // channel with a max capacity this can put the queue over it for a
+    // moment, if a sender took the space in the meantime.
+    pub fn requeue_front(&self, message: T) {
+        let mut b = self.queue.lock().unwrap();
+        b.push_front(message);
+        drop(b);
+        self.notify_sent();
+    }
+
+    // The average time receive (or receive_pair) spent blocked, over the
+    // receives that had to wait at all, in nanoseconds. Receives that found a
+    // message right away aren't counted. 0 if none had to wait yet.
+    #[cfg(feature = "wait-stats")]
+    pub fn average_wait_nanos(&self) -> u64 {
+        self.wait_stats.average_nanos()
+    }
+
+    // How many more notifications went out than messages were received, see
+    // NotifySeq in debug.rs. Once no send or receive is running, this has to
+    // be exactly how many messages are queued, fewer means a message was
+    // queued without a notification, a lost wakeup. Only in debug builds.
+    #[cfg(debug_assertions)]
+    pub fn unconsumed_notifications(&self) -> i64 {
+        let _b = self.queue.lock().unwrap();
+        self.notify_seq.unconsumed()
     }
 
     // Blocks current thread until mutex is acquired and locked, pops message from
     // front of queue, but will use condition variable to wait if no message
     // available yet.
+    // Panics instead of waiting forever once the channel is closed and
+    // nothing is left in the queue. channel()'s Receiver doesn't use this, so
+    // that only happens after close.
     pub fn receive(&self) -> T {
+        self.receive_unless_disconnected().expect("Channel was closed!")
+    }
+
+    // Gives None instead of waiting forever once every Sender is gone and
+    // nothing is left in the queue
+    fn receive_unless_disconnected(&self) -> Option<T> {
+        let mut b = self.lock_non_empty()?;
+        let message = b.pop_front().unwrap();
+        self.notify_seq.consumed(1);
+        drop(b);
+        self.notify_received(1);
+        Some(message)
+    }
+
+    // Like receive, but if there's a second message queued right behind the
+    // first, takes that one too, in the same locked operation.
+    // Doesn't wait for a second message.
+    pub fn receive_pair(&self) -> (T, Option<T>) {
+        let mut b = self.lock_non_empty().expect("Channel was closed!");
+        let first = b.pop_front().unwrap();
+        let second = b.pop_front();
+        self.notify_seq.consumed(1 + second.is_some() as usize);
+        drop(b);
+        self.notify_received(1 + second.is_some() as usize);
+        (first, second)
+    }
+
+    // How many messages are queued right now. Just a snapshot, other
+    // threads can send and receive as soon as the lock is released, so it
+    // may be out of date by the time the caller acts on it. Fine for logging,
+    // metrics and tests, not for deciding whether a receive would block.
+    pub fn len(&self) -> usize {
+        self.queue.lock().unwrap().len()
+    }
+
+    // A snapshot too, like len
+    pub fn is_empty(&self) -> bool {
+        self.queue.lock().unwrap().is_empty()
+    }
+
+    // Like receive, but returns None right away if there's no message.
+    // When there's nothing, this only locks and unlocks the queue, it never
+    // waits on or notifies either condvar. The lock is only held to pop.
+    pub fn try_receive(&self) -> Option<T> {
         let mut b = self.queue.lock().unwrap();
-        loop {
+        let message = b.pop_front()?;
+        self.notify_seq.consumed(1);
+        drop(b);
+        self.notify_received(1);
+        Some(message)
+    }
+
+    // Like receive, but gives up and returns None if no message arrives
+    // within timeout.
+    pub fn receive_timeout(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+        let mut b = self.queue.lock().unwrap();
+        let message = loop {
             if let Some(message) = b.pop_front() {
-                return message
+                self.notify_seq.consumed(1);
+                break message;
+            }
+            // Loop in case of spurious wakeups, or another receiver taking the
+            // message before us, with whatever time is left
+            let left = deadline.checked_duration_since(Instant::now())?;
+            b = self.item_ready.wait_timeout(b, left).unwrap().0;
+        };
+        drop(b);
+        self.notify_received(1);
+        Some(message)
+    }
+
+    // Like receive_timeout, but says why there's no message: Timeout, or
+    // Disconnected when the queue is empty and every Sender from channel()
+    // is gone, which returns right away instead of waiting out the timeout.
+    // wait_timeout_while keeps waiting on spurious wakeups (or when another
+    // receiver gets there first) with whatever time is left, not the whole
+    // timeout again.
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        let b = self.queue.lock().unwrap();
+        let (mut b, _) = self
+            .item_ready
+            .wait_timeout_while(b, timeout, |b| b.is_empty() && !self.disconnected.load(Relaxed))
+            .unwrap();
+        let Some(message) = b.pop_front() else {
+            if self.disconnected.load(Relaxed) {
+                return Err(RecvTimeoutError::Disconnected);
+            }
+            return Err(RecvTimeoutError::Timeout);
+        };
+        self.notify_seq.consumed(1);
+        drop(b);
+        self.notify_received(1);
+        Ok(message)
+    }
+
+    // Takes a message and gives it to f, which either processes it and returns
+    // Ok, or rejects it by giving it back in Err. A rejected message is put back
+    // where it was, so other receivers can still get it, and we move on to the
+    // message behind it. That way a message f keeps rejecting doesn't stop us
+    // from getting to the other messages.
+    // Once f has rejected every queued message, we wait for a new message, or
+    // for RETRY_INTERVAL at most, before going through the queue again.
+    // f runs without the lock held. FnMut as it may be called more than once.
+    // Panics like receive once the channel is closed (or every Sender is gone)
+    // and the queue is empty. Messages f rejected are still tried again after
+    // that, as f might take them later, they're all that's left to get.
+    pub fn receive_try_process<R>(&self, mut f: impl FnMut(T) -> Result<R, T>) -> R {
+        const RETRY_INTERVAL: Duration = Duration::from_millis(10);
+        // How many messages at the front we've already rejected on this pass
+        let mut skip = 0;
+        let mut b = self.queue.lock().unwrap();
+        loop {
+            if skip >= b.len() {
+                if b.is_empty() && self.disconnected.load(Relaxed) {
+                    // Unlocked first, so the panic doesn't poison the queue
+                    drop(b);
+                    panic!("Channel was closed!");
+                }
+                // Everything queued was rejected (or there's nothing), wait
+                if skip == 0 {
+                    b = self.item_ready.wait(b).unwrap();
+                } else {
+                    b = self.item_ready.wait_timeout(b, RETRY_INTERVAL).unwrap().0;
+                    skip = 0;
+                }
+                continue;
+            }
+            let message = b.remove(skip).unwrap();
+            self.notify_seq.consumed(1);
+            drop(b);
+            self.notify_received(1);
+            match f(message) {
+                Ok(result) => return result,
+                Err(message) => {
+                    b = self.queue.lock().unwrap();
+                    // Other receivers might have taken messages in the meantime,
+                    // so this is as close to where it was as we can get.
+                    // A sender might have taken its space too, so this can put
+                    // the queue over max capacity for a moment.
+                    let index = skip.min(b.len());
+                    b.insert(index, message);
+                    skip = index + 1;
+                    self.item_ready.notify_one();
+                    self.notify_seq.notified();
+                }
+            }
+        }
+    }
+
+    // Moves every message that's currently queued onto the end of out, in the
+    // order they were sent, and returns how many were moved.
+    // Doesn't wait if the queue is empty. Reusing the same out between calls
+    // means no allocation once it has grown large enough.
+    pub fn drain_into(&self, out: &mut Vec<T>) -> usize {
+        let mut b = self.queue.lock().unwrap();
+        let n = b.len();
+        out.extend(b.drain(..));
+        self.notify_seq.consumed(n);
+        drop(b);
+        self.notify_received(n);
+        n
+    }
+
+    // Takes every message that's currently queued, in the order they were
+    // sent. Doesn't wait if the queue is empty, that just gives an empty Vec.
+    // Unlike drain_into, the lock is only held to swap the queue out for an
+    // empty one, turning it into a Vec happens after unlocking. The queue
+    // starts over with no room allocated, so the next sends have to grow it
+    // again.
+    pub fn drain(&self) -> Vec<T> {
+        Vec::from(self.take_queue())
+    }
+
+    // Folds every message that's currently queued into init, in the order
+    // they were sent, like drain().into_iter().fold(init, f), without
+    // building the Vec. The queue is swapped out like in drain, so f runs
+    // after unlocking, and can take as long as it likes.
+    pub fn drain_fold<B>(&self, init: B, f: impl FnMut(B, T) -> B) -> B {
+        self.take_queue().into_iter().fold(init, f)
+    }
+
+    // Swaps the queue out for an empty one, for drain and drain_fold
+    fn take_queue(&self) -> VecDeque<T> {
+        let mut b = self.queue.lock().unwrap();
+        let taken = std::mem::take(&mut *b);
+        self.notify_seq.consumed(taken.len());
+        drop(b);
+        self.notify_received(taken.len());
+        taken
+    }
+
+    // For a thread holding a SpinLock that needs to wait for a message.
+    // Unlocks the spin lock while waiting, then locks it again before returning,
+    // like Condvar::wait does with a Mutex. Holding on to a spin lock while
+    // blocked would leave every other thread that wants it spinning the whole
+    // time, or deadlock if the sender needs it.
+    pub fn receive_while_unlocked<'a, G>(&self, guard: SpinLockGuard<'a, G>) -> (T, SpinLockGuard<'a, G>) {
+        SpinLockGuard::unlocked(guard, || self.receive())
+    }
+
+    // Splits the channel into a half that can only send and a half that can
+    // only receive, so code that's only meant to do one of them can't do the
+    // other. Both can be cloned, for any number of senders and receivers.
+    pub fn into_split(self) -> (WriteHalf<T>, ReadHalf<T>) {
+        let channel = Arc::new(self);
+        (WriteHalf { channel: channel.clone() }, ReadHalf { channel })
+    }
+
+    // Waits until there's a message for us, and returns the locked queue,
+    // which is guaranteed to not be empty. Returns None instead if the queue
+    // is empty and the channel is closed (or every Sender is gone), as nothing
+    // would ever arrive.
+    // On a fair channel, we take a ticket and also wait for our turn. The ticket
+    // is taken while holding the lock, so the ticket order is the order in which
+    // receivers got the lock.
+    // Giving up also waits for our turn, and then passes the turn on, the same
+    // as taking a message. A ticket that's skipped would never come up again,
+    // leaving every later receiver waiting forever, even once close lets new
+    // messages in.
+    fn lock_non_empty(&self) -> Option<MutexGuard<'_, VecDeque<T>>> {
+        let mut b = self.queue.lock().unwrap();
+        let ticket = self.fair.then(|| self.next_ticket.fetch_add(1, Relaxed));
+        let is_turn = || ticket.is_none_or(|t| self.now_serving.load(Relaxed) == t);
+        let our_turn = |b: &VecDeque<T>| !b.is_empty() && is_turn();
+        let gone = |b: &VecDeque<T>| b.is_empty() && self.disconnected.load(Relaxed) && is_turn();
+        if !our_turn(&b) {
+            self.watch.start_waiting();
+            let waited = self.wait_stats.timed(|| {
+                while !our_turn(&b) {
+                    if gone(&b) {
+                        if let Some(t) = ticket {
+                            self.now_serving.store(t + 1, Relaxed);
+                            self.item_ready.notify_all();
+                        }
+                        return None;
+                    }
+                    b = self.item_ready.wait(b).unwrap();
+                }
+                Some(b)
+            });
+            self.watch.stop_waiting();
+            b = waited?;
+        }
+        if let Some(t) = ticket {
+            self.now_serving.store(t + 1, Relaxed);
+        }
+        Some(b)
+    }
+
+    // Tells every receiver nothing more is coming: once the queue is empty,
+    // waiting receives give up, like after the last Sender from channel() is
+    // dropped. Messages that are already queued can still be received.
+    // Doesn't stop sends, anything sent after this is queued as usual, for
+    // drain to clean up.
+    pub fn close(&self) {
+        self.disconnect();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.disconnected.load(Relaxed)
+    }
+
+    // Called by the last Sender's Drop, and close
+    fn disconnect(&self) {
+        let b = self.queue.lock().unwrap();
+        self.disconnected.store(true, Relaxed);
+        drop(b);
+        // Everyone waiting has to find out
+        self.item_ready.notify_all();
+    }
+
+    // Call after putting a message in the queue, once it's unlocked.
+    // Normally one waiting receiver is enough, but see new_fair and
+    // receive_min_batch for when everyone has to be woken up.
+    fn notify_sent(&self) {
+        if self.fair || self.batch_waiters.load(Relaxed) > 0 {
+            self.item_ready.notify_all();
+        } else {
+            self.item_ready.notify_one();
+        }
+        self.notify_seq.notified();
+        if self.observer_count.load(Relaxed) > 0 {
+            for thread in self.observers.lock().unwrap().iter() {
+                wake::unpark(thread);
+            }
+        }
+    }
+
+    // From now on, thread is unparked after every send, until it's removed
+    // again. Check the queue after adding it, not before: a send that comes
+    // after our check locks the queue after we did, so it's guaranteed to see
+    // observer_count go up, while one before it is seen by the check.
+    pub(crate) fn add_observer(&self, thread: Thread) {
+        let mut observers = self.observers.lock().unwrap();
+        observers.push(thread);
+        self.observer_count.store(observers.len(), Relaxed);
+    }
+
+    pub(crate) fn remove_observer(&self, thread: &Thread) {
+        let mut observers = self.observers.lock().unwrap();
+        if let Some(i) = observers.iter().position(|t| t.id() == thread.id()) {
+            observers.swap_remove(i);
+        }
+        self.observer_count.store(observers.len(), Relaxed);
+    }
+
+    // Call after taking n messages out of the queue, once it's unlocked.
+    // On a fair channel, after a receiver is served, the next receiver in line
+    // might be able to take a message that's already queued, so wake them all
+    // up to find out who's next.
+    // On a channel with a max capacity, there's now room for n more messages.
+    fn notify_received(&self, n: usize) {
+        if self.fair {
+            self.item_ready.notify_all();
+        }
+        if self.max_capacity.is_some() {
+            match n {
+                0 => {}
+                1 => self.space_available.notify_one(),
+                _ => self.space_available.notify_all(),
+            }
+        }
+    }
+}
+
+// From Channel::reserve. Dropping it without sending gives the slots back.
+pub struct Reservation<'a, T> {
+    channel: &'a Channel<T>,
+    slots: usize,
+}
+
+impl<T> Reservation<'_, T> {
+    pub fn slots(&self) -> usize {
+        self.slots
+    }
+
+    // Sends the messages in order, in one go, so no other sender's messages
+    // end up in between. Never waits, the slots are already ours. Any slots
+    // left over are given back.
+    // Panics if there are more messages than slots, without sending any.
+    pub fn send_all(self, messages: impl IntoIterator<Item = T>) {
+        // Collected first, so a panicking iterator, or too many messages,
+        // can't leave the queue locked or half filled
+        let messages: Vec<T> = messages.into_iter().collect();
+        assert!(messages.len() <= self.slots, "More messages than reserved slots!");
+        let channel = self.channel;
+        let mut b = channel.queue.lock().unwrap();
+        let n = messages.len();
+        for message in messages {
+            b.push_back(message);
+        }
+        channel.reserved.fetch_sub(self.slots, Relaxed);
+        let unused = self.slots - n;
+        drop(b);
+        std::mem::forget(self);
+        for _ in 0..n {
+            channel.notify_sent();
+        }
+        // Leftover slots are room for waiting senders
+        channel.notify_received(unused);
+    }
+}
+
+impl<T> Drop for Reservation<'_, T> {
+    fn drop(&mut self) {
+        let b = self.channel.queue.lock().unwrap_or_else(|e| e.into_inner());
+        self.channel.reserved.fetch_sub(self.slots, Relaxed);
+        drop(b);
+        self.channel.notify_received(self.slots);
+    }
+}
+
+// From Channel::into_split. Only sends, the type doesn't have a receive:
+/// ```compile_fail
+/// use rust_atomics_and_locks::channels::mutex_based::Channel;
+/// let (write, _read) = Channel::<u32>::new().into_split();
+/// write.receive();
+/// ```
+pub struct WriteHalf<T> {
+    channel: Arc<Channel<T>>,
+}
+
+// Only receives:
+/// ```compile_fail
+/// use rust_atomics_and_locks::channels::mutex_based::Channel;
+/// let (_write, read) = Channel::<u32>::new().into_split();
+/// read.send(1);
+/// ```
+pub struct ReadHalf<T> {
+    channel: Arc<Channel<T>>,
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for WriteHalf<T> {
+    fn clone(&self) -> Self {
+        Self { channel: self.channel.clone() }
+    }
+}
+
+impl<T> Clone for ReadHalf<T> {
+    fn clone(&self) -> Self {
+        Self { channel: self.channel.clone() }
+    }
+}
+
+impl<T> WriteHalf<T> {
+    pub fn send(&self, message: T) {
+        self.channel.send(message);
+    }
+
+    pub fn send_all(&self, messages: impl IntoIterator<Item = T>) {
+        self.channel.send_all(messages);
+    }
+}
+
+impl<T> ReadHalf<T> {
+    pub fn receive(&self) -> T {
+        self.channel.receive()
+    }
+
+    pub fn try_receive(&self) -> Option<T> {
+        self.channel.try_receive()
+    }
+}
+
+// A Channel that always has a max capacity, for when the queue must never
+// grow without bounds (con 3 above). send blocks while capacity messages are
+// queued, until a receiver makes room, which is what with_config does with a
+// max capacity, this just makes it impossible to forget.
+// Both sides re-check their condition in a loop after every wakeup, so a
+// spurious wakeup, or another thread getting there first, can't make them
+// go ahead when they shouldn't.
+pub struct BoundedChannel<T> {
+    channel: Channel<T>,
+}
+
+impl<T> BoundedChannel<T> {
+    // Panics if capacity is zero, the minimum is 1
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "Capacity must be at least 1!");
+        Self { channel: Channel::with_config(capacity, Some(capacity)) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.channel.max_capacity.unwrap()
+    }
+
+    // Blocks while the channel is full
+    pub fn send(&self, message: T) {
+        self.channel.send(message);
+    }
+
+    // Blocks while the channel is full, for timeout at most, gives the message
+    // back if there was no room in time
+    pub fn send_timeout(&self, message: T, timeout: Duration) -> Result<(), T> {
+        self.channel.send_timeout(message, timeout)
+    }
+
+    // None if n slots aren't free right now, see Channel::reserve
+    pub fn reserve(&self, n: usize) -> Option<Reservation<'_, T>> {
+        self.channel.reserve(n)
+    }
+
+    // Blocks while the channel is empty, wakes up a blocked sender after
+    pub fn receive(&self) -> T {
+        self.channel.receive()
+    }
+
+    pub fn try_receive(&self) -> Option<T> {
+        self.channel.try_receive()
+    }
+}
+
+// An owned pair of handles to a shared Channel, like std's channels.
+// Both can be cloned, for any number of senders and receivers.
+// Once every Sender is dropped, receive gives an error instead of waiting
+// forever, after the messages that were still queued have been received.
+pub fn channel<T>() -> (Sender<T>, Receiver<T>) {
+    let shared = Arc::new(Shared { channel: Channel::new(), senders: AtomicUsize::new(1) });
+    (Sender { shared: shared.clone() }, Receiver { shared })
+}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+// From receive. An enum like RecvTimeoutError, though waiting without a
+// timeout can only end one way without a message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvError {
+    // Every Sender is gone, and nothing is left to receive
+    Disconnected,
+}
+
+// From recv_timeout
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvTimeoutError {
+    // No message arrived in time
+    Timeout,
+    // Every Sender is gone, and nothing is left to receive
+    Disconnected,
+}
+
+struct Shared<T> {
+    channel: Channel<T>,
+    // How many Senders there are. Nothing is synchronized through this, the
+    // last one disconnects through the queue lock, so Relaxed is enough
+    senders: AtomicUsize,
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders.fetch_add(1, Relaxed);
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders.fetch_sub(1, Relaxed) == 1 {
+            self.shared.channel.disconnect();
+        }
+    }
+}
+
+// Not derived, as that would require T: Clone
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self { shared: self.shared.clone() }
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn send(&self, message: T) {
+        self.shared.channel.send(message);
+    }
+}
+
+impl<T> Receiver<T> {
+    // Blocks until there's a message, or every Sender is gone. Messages sent
+    // before the last Sender was dropped are all received first, it's only
+    // Disconnected once the queue is empty too.
+    pub fn receive(&self) -> Result<T, RecvError> {
+        self.shared.channel.receive_unless_disconnected().ok_or(RecvError::Disconnected)
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<T, RecvTimeoutError> {
+        self.shared.channel.recv_timeout(timeout)
+    }
+
+    // Never blocks, None if the queue is empty, whether or not every Sender
+    // is gone
+    pub fn try_receive(&self) -> Option<T> {
+        self.shared.channel.try_receive()
+    }
+
+    // The messages that are queued right now, stops at the first empty queue
+    // instead of waiting for more
+    pub fn try_iter(&self) -> impl Iterator<Item = T> + '_ {
+        std::iter::from_fn(|| self.try_receive())
+    }
+}
+
+// Blocks for every next message, ends once every Sender is gone and the queue
+// is empty, so for message in receiver works like it does with std's channels
+impl<T> Iterator for Receiver<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        self.receive().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::thread;
+
+    // Waits for the receivers to have taken n tickets, so we know which order
+    // they're blocked in
+    fn wait_for_tickets<T>(c: &Channel<T>, n: usize) {
+        while c.next_ticket.load(Relaxed) < n {
+            thread::yield_now();
+        }
+    }
+
+    #[test]
+    fn fair_serves_receivers_in_blocking_order() {
+        let c = Channel::new_fair();
+        let order = Mutex::new(Vec::new());
+        thread::scope(|s| {
+            for i in 0..3 {
+                let (c, order) = (&c, &order);
+                s.spawn(move || {
+                    let m = c.receive();
+                    order.lock().unwrap().push((i, m));
+                });
+                wait_for_tickets(c, i + 1);
+            }
+            for m in 0..3 {
+                c.send(m);
+                while order.lock().unwrap().len() <= m {
+                    thread::yield_now();
+                }
+            }
+        });
+        assert_eq!(*order.lock().unwrap(), [(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn fair_receivers_giving_up_pass_their_turn_on() {
+        let c = Channel::<u32>::new_fair();
+        thread::scope(|s| {
+            let waiting: Vec<_> = (0..3)
+                .map(|i| {
+                    let h = s.spawn(|| panic::catch_unwind(AssertUnwindSafe(|| c.receive())).is_err());
+                    wait_for_tickets(&c, i + 1);
+                    h
+                })
+                .collect();
+            c.close();
+            for h in waiting {
+                // Gave up, receive panics on a closed channel
+                assert!(h.join().unwrap());
+            }
+        });
+        // close still lets messages in, and the next receiver's ticket has to
+        // come up for it to get one
+        c.send(1);
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn drain_into_reuses_the_vec() {
+        let c = Channel::new();
+        let mut out = Vec::new();
+        for round in 0..3 {
+            for i in 0..10 {
+                c.send(i);
+            }
+            let capacity = out.capacity();
+            let ptr = out.as_ptr();
+            assert_eq!(c.drain_into(&mut out), 10);
+            if round > 0 {
+                // Big enough since the first round, so not reallocated
+                assert_eq!((out.capacity(), out.as_ptr()), (capacity, ptr));
+            }
+            assert_eq!(out, (0..10).collect::<Vec<_>>());
+            out.clear();
+        }
+        assert_eq!(c.drain_into(&mut out), 0);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn drain_into_appends() {
+        let c = Channel::new();
+        let mut out = vec![-1];
+        c.send(1);
+        c.send(2);
+        assert_eq!(c.drain_into(&mut out), 2);
+        assert_eq!(out, [-1, 1, 2]);
+    }
+
+    #[test]
+    fn receive_while_unlocked_lets_others_take_the_lock() {
+        use crate::locks::spin_lock::SpinLock;
+        let lock = SpinLock::new(0);
+        let c = Channel::new();
+        thread::scope(|s| {
+            let guard = lock.lock();
+            s.spawn(|| {
+                // Only gets the lock because the receiver let go of it while
+                // waiting, otherwise this spins forever
+                *lock.lock() += 1;
+                c.send("sent");
+            });
+            let (message, guard) = c.receive_while_unlocked(guard);
+            assert_eq!((message, *guard), ("sent", 1));
+        });
+    }
+
+    #[test]
+    fn receive_pair_takes_up_to_two() {
+        for c in [Channel::new(), Channel::new_fair()] {
+            for i in 1..=3 {
+                c.send(i);
             }
-            b = self.item_ready.wait(b).unwrap();
+            assert_eq!(c.receive_pair(), (1, Some(2)));
+            assert_eq!(c.receive_pair(), (3, None));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn receive_pair_waits_for_the_first_only() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive_pair());
+            thread::sleep(Duration::from_millis(10));
+            c.send(1);
+            assert_eq!(h.join().unwrap(), (1, None));
+        });
+    }
+
+    #[test]
+    fn with_config_doesnt_reallocate_up_to_initial_capacity() {
+        let c = Channel::with_config(16, None);
+        let capacity = c.queue.lock().unwrap().capacity();
+        assert!(capacity >= 16);
+        for i in 0..16 {
+            c.send(i);
+        }
+        assert_eq!(c.queue.lock().unwrap().capacity(), capacity);
+        assert_eq!(c.receive(), 0);
+    }
+
+    #[test]
+    fn with_config_max_capacity_makes_senders_wait() {
+        let c = Channel::with_config(0, Some(2));
+        c.send(1);
+        c.send(2);
+        assert_eq!(c.send_timeout(3, Duration::from_millis(10)), Err(3));
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(20));
+                assert_eq!(c.receive(), 1);
+            });
+            // Waits for the receiver to make room
+            c.send(3);
+        });
+        assert_eq!(c.receive_pair(), (2, Some(3)));
+    }
+
+    // Records its number when dropped, and panics doing so if told to
+    struct Noisy<'a>(i32, &'a Mutex<Vec<i32>>, bool);
+
+    impl Drop for Noisy<'_> {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+            if self.2 {
+                panic!("Noisy {} panicked", self.0);
+            }
+        }
+    }
+
+    #[test]
+    fn unreceived_messages_drop_front_to_back() {
+        let log = Mutex::new(Vec::new());
+        let c = Channel::new();
+        for i in 0..4 {
+            c.send(Noisy(i, &log, false));
+        }
+        drop(c.receive());
+        drop(c);
+        assert_eq!(*log.lock().unwrap(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn panicking_message_drop_still_drops_the_rest() {
+        let log = Mutex::new(Vec::new());
+        let c = Channel::new();
+        for i in 0..5 {
+            c.send(Noisy(i, &log, i == 2));
+        }
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| drop(c))).is_err());
+        // Each dropped exactly once, in order
+        assert_eq!(*log.lock().unwrap(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn receive_try_process_retries_a_rejected_message() {
+        let c = Channel::new();
+        c.send("first");
+        let mut tries = Vec::new();
+        let result = c.receive_try_process(|m| {
+            tries.push(m);
+            if tries.len() == 1 { Err(m) } else { Ok(m.len()) }
+        });
+        assert_eq!(result, 5);
+        assert_eq!(tries, ["first", "first"]);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn receive_try_process_moves_past_a_rejected_message() {
+        let c = Channel::new();
+        c.send(1);
+        c.send(2);
+        assert_eq!(c.receive_try_process(|m| if m == 1 { Err(m) } else { Ok(m) }), 2);
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn receive_try_process_gives_up_once_closed() {
+        let c = Channel::<i32>::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| panic::catch_unwind(AssertUnwindSafe(|| c.receive_try_process(Ok))).is_err());
+            thread::sleep(Duration::from_millis(10));
+            c.close();
+            assert!(h.join().unwrap());
+        });
+        // Still takes what was queued before giving up
+        c.send(1);
+        assert_eq!(c.receive_try_process(Ok), 1);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.receive_try_process(Ok))).is_err());
+        // The queue isn't poisoned
+        c.send(2);
+        assert_eq!(c.receive(), 2);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn every_message_is_notified_about() {
+        const PER_THREAD: usize = if cfg!(miri) { 100 } else { 10_000 };
+        for c in [Channel::new(), Channel::new_fair(), Channel::with_config(0, Some(16))] {
+            thread::scope(|s| {
+                for sender in 0..4 {
+                    let c = &c;
+                    s.spawn(move || {
+                        if sender % 2 == 0 {
+                            for i in 0..PER_THREAD {
+                                c.send(i);
+                            }
+                        } else {
+                            for chunk in (0..PER_THREAD).collect::<Vec<_>>().chunks(10) {
+                                c.send_all(chunk.iter().copied());
+                            }
+                        }
+                    });
+                    s.spawn(|| {
+                        for _ in 0..PER_THREAD {
+                            c.receive();
+                        }
+                    });
+                }
+            });
+            assert!(c.is_empty());
+            assert_eq!(c.unconsumed_notifications(), 0);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn unconsumed_notifications_match_whats_queued() {
+        let c = Channel::new();
+        for i in 0..3 {
+            c.send(i);
+        }
+        c.requeue_front(c.receive());
+        assert_eq!(c.unconsumed_notifications(), 3);
+        c.drain();
+        assert_eq!(c.unconsumed_notifications(), 0);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    fn a_message_queued_without_notifying_shows_up_as_a_gap() {
+        let c = Channel::new();
+        c.send(1);
+        // What a send path that forgot to notify would do
+        c.queue.lock().unwrap().push_back(2);
+        assert_eq!(c.unconsumed_notifications(), 1);
+        assert_eq!(c.len(), 2);
+    }
+
+    #[test]
+    fn requeued_message_is_received_next() {
+        let c = Channel::new();
+        c.send("retry me");
+        thread::scope(|s| {
+            s.spawn(|| {
+                let m = c.receive();
+                c.send("later");
+                c.requeue_front(m);
+            })
+            .join()
+            .unwrap();
+            let second = s.spawn(|| c.receive());
+            assert_eq!(second.join().unwrap(), "retry me");
+        });
+        assert_eq!(c.receive(), "later");
+    }
+
+    #[test]
+    fn requeue_front_wakes_a_receiver() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive());
+            thread::sleep(Duration::from_millis(10));
+            c.requeue_front(1);
+            assert_eq!(h.join().unwrap(), 1);
+        });
+    }
+
+    #[test]
+    fn split_halves_talk_to_each_other() {
+        let (write, read) = Channel::new().into_split();
+        let (write2, read2) = (write.clone(), read.clone());
+        thread::scope(|s| {
+            s.spawn(move || write2.send_all([1, 2]));
+        });
+        write.send(3);
+        assert_eq!((read.receive(), read2.receive()), (1, 2));
+        assert_eq!(read2.try_receive(), Some(3));
+        assert_eq!(read.try_receive(), None);
+    }
+
+    #[test]
+    fn receive_min_batch_waits_for_min() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive_min_batch(3, 10));
+            for i in 0..3 {
+                thread::sleep(Duration::from_millis(5));
+                assert!(!h.is_finished());
+                c.send(i);
+            }
+            assert_eq!(h.join().unwrap(), [0, 1, 2]);
+        });
+    }
+
+    #[test]
+    fn receive_min_batch_takes_at_most_max() {
+        let c = Channel::new();
+        c.send_all(0..5);
+        assert_eq!(c.receive_min_batch(1, 3), [0, 1, 2]);
+        assert_eq!(c.receive_min_batch(2, 3), [3, 4]);
+    }
+
+    #[test]
+    fn receive_min_batch_timeout_takes_what_there_is() {
+        let c = Channel::new();
+        c.send(1);
+        assert_eq!(c.receive_min_batch_timeout(3, 3, Duration::from_millis(10)), [1]);
+        assert!(c.receive_min_batch_timeout(1, 3, Duration::from_millis(10)).is_empty());
+    }
+
+    #[test]
+    fn cloned_senders_all_reach_the_receivers() {
+        let (s, r) = channel();
+        let r2 = r.clone();
+        thread::scope(|scope| {
+            for i in 0..3 {
+                let s = s.clone();
+                scope.spawn(move || s.send(i));
+            }
+        });
+        drop(s);
+        let mut got = vec![r.receive().unwrap(), r2.receive().unwrap(), r.receive().unwrap()];
+        got.sort();
+        assert_eq!(got, [0, 1, 2]);
+        assert_eq!(r2.receive(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn dropping_the_last_sender_wakes_a_blocked_receiver() {
+        let (s, r) = channel::<i32>();
+        let s2 = s.clone();
+        thread::scope(|scope| {
+            let h = scope.spawn(|| r.receive());
+            thread::sleep(Duration::from_millis(10));
+            drop(s);
+            thread::sleep(Duration::from_millis(10));
+            assert!(!h.is_finished());
+            s2.send(1);
+            drop(s2);
+            assert_eq!(h.join().unwrap(), Ok(1));
+        });
+        assert_eq!(r.receive(), Err(RecvError::Disconnected));
+        let (s, r) = channel::<i32>();
+        thread::scope(|scope| {
+            let h = scope.spawn(|| r.receive());
+            thread::sleep(Duration::from_millis(10));
+            drop(s);
+            assert_eq!(h.join().unwrap(), Err(RecvError::Disconnected));
+        });
+    }
+
+    #[test]
+    fn queued_messages_are_received_before_disconnected() {
+        let (s, r) = channel();
+        s.send("a");
+        s.send("b");
+        drop(s);
+        assert_eq!(r.receive(), Ok("a"));
+        assert_eq!(r.receive(), Ok("b"));
+        assert_eq!(r.receive(), Err(RecvError::Disconnected));
+    }
+
+    #[test]
+    fn try_receive_doesnt_wait() {
+        let c = Channel::new();
+        c.send(1);
+        c.send(2);
+        assert_eq!(c.try_receive(), Some(1));
+        assert_eq!(c.try_receive(), Some(2));
+        assert_eq!(c.try_receive(), None);
+    }
+
+    #[test]
+    fn single_slot_sender_waits_for_the_receiver() {
+        let c = BoundedChannel::new(1);
+        assert_eq!(c.capacity(), 1);
+        c.send(1);
+        thread::scope(|s| {
+            let h = s.spawn(|| c.send(2));
+            thread::sleep(Duration::from_millis(20));
+            assert!(!h.is_finished());
+            assert_eq!(c.receive(), 1);
+            h.join().unwrap();
+        });
+        assert_eq!(c.try_receive(), Some(2));
+        assert_eq!(c.try_receive(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 1!")]
+    fn bounded_capacity_zero_panics() {
+        BoundedChannel::<i32>::new(0);
+    }
+
+    #[test]
+    fn send_timeout_on_a_full_channel_gives_the_message_back() {
+        let c = BoundedChannel::new(1);
+        c.send(String::from("first"));
+        let start = Instant::now();
+        assert_eq!(c.send_timeout(String::from("late"), Duration::from_millis(50)).unwrap_err(), "late");
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(c.receive(), "first");
+        assert_eq!(c.try_receive(), None);
+    }
+
+    #[test]
+    fn send_timeout_goes_ahead_once_there_is_room() {
+        let c = BoundedChannel::new(1);
+        c.send(1);
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                assert_eq!(c.receive(), 1);
+            });
+            assert_eq!(c.send_timeout(2, Duration::from_secs(10)), Ok(()));
+        });
+        assert_eq!(c.receive(), 2);
+    }
+
+    #[test]
+    fn receiver_iterates_until_every_sender_is_gone() {
+        let (s, r) = channel();
+        thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..5 {
+                    s.send(i);
+                }
+            });
+        });
+        assert_eq!(r.collect::<Vec<_>>(), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn try_iter_stops_at_the_first_empty_queue() {
+        let (s, r) = channel();
+        s.send(1);
+        s.send(2);
+        assert_eq!(r.try_iter().collect::<Vec<_>>(), [1, 2]);
+        // Still connected, just empty for now
+        s.send(3);
+        assert_eq!(r.try_iter().collect::<Vec<_>>(), [3]);
+    }
+
+    #[test]
+    fn default_is_an_empty_channel() {
+        fn default_of<C: Default>() -> C {
+            C::default()
+        }
+        let c: Channel<u32> = default_of();
+        assert!(c.is_empty());
+        c.send(1);
+        assert_eq!(c.receive(), 1);
+        // Embedding a channel in a struct that derives Default
+        #[derive(Default)]
+        struct Subsystem {
+            jobs: Channel<u32>,
+            done: crate::channels::safer_oneshot::Channel<()>,
+        }
+        let s = Subsystem::default();
+        assert_eq!(s.jobs.try_receive(), None);
+        assert!(!s.done.is_ready());
+    }
+
+    #[test]
+    fn reservation_holds_its_slots() {
+        let c = BoundedChannel::new(4);
+        let batch = c.reserve(3).unwrap();
+        assert_eq!(batch.slots(), 3);
+        thread::scope(|s| {
+            // Only one slot left for anyone else
+            assert!(s.spawn(|| c.reserve(2).is_none()).join().unwrap());
+        });
+        c.send(0);
+        assert_eq!(c.send_timeout(9, Duration::from_millis(10)), Err(9));
+        batch.send_all([1, 2, 3]);
+        assert_eq!(c.channel.drain(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn unused_reserved_slots_are_given_back() {
+        let c = BoundedChannel::new(2);
+        let batch = c.reserve(2).unwrap();
+        assert!(c.reserve(1).is_none());
+        batch.send_all([1]);
+        assert!(c.reserve(1).is_some());
+        drop(c.reserve(1));
+        assert!(c.reserve(2).is_none());
+        assert_eq!(c.receive(), 1);
+        assert!(c.reserve(2).is_some());
+    }
+
+    #[test]
+    fn too_many_messages_for_a_reservation_sends_none() {
+        let c = BoundedChannel::new(4);
+        let batch = c.reserve(1).unwrap();
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| batch.send_all([1, 2]))).is_err());
+        assert_eq!(c.try_receive(), None);
+        assert!(c.reserve(4).is_some());
+    }
+
+    #[test]
+    fn len_counts_whats_queued() {
+        let c = Channel::new();
+        assert_eq!(c.len(), 0);
+        assert!(c.is_empty());
+        for i in 0..3 {
+            c.send(i);
+        }
+        assert_eq!(c.len(), 3);
+        assert!(!c.is_empty());
+        c.receive();
+        assert_eq!(c.len(), 2);
+    }
+
+    #[test]
+    fn drain_takes_everything_in_order() {
+        let c = Channel::new();
+        assert!(c.drain().is_empty());
+        for i in 0..5 {
+            c.send(i);
+        }
+        assert_eq!(c.drain(), [0, 1, 2, 3, 4]);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn drain_makes_room_for_a_waiting_sender() {
+        let c = BoundedChannel::new(2);
+        c.send(1);
+        c.send(2);
+        thread::scope(|s| {
+            let h = s.spawn(|| c.send(3));
+            thread::sleep(Duration::from_millis(10));
+            assert_eq!(c.channel.drain(), [1, 2]);
+            h.join().unwrap();
+        });
+        assert_eq!(c.receive(), 3);
+    }
+
+    #[test]
+    fn drain_fold_sums_whats_queued() {
+        let c = Channel::new();
+        for i in 1..=5 {
+            c.send(i);
+        }
+        assert_eq!(c.drain_fold(0, |sum, i| sum + i), 15);
+        assert!(c.is_empty());
+        assert_eq!(c.drain_fold(0, |n, _| n + 1), 0);
+        // In the order they were sent
+        let c = Channel::new();
+        c.send("a");
+        c.send("b");
+        assert_eq!(c.drain_fold(String::new(), |s, m| s + m), "ab");
+    }
+
+    #[test]
+    fn recv_timeout_gets_a_message_sent_in_time() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(30));
+                c.send(1);
+            });
+            assert_eq!(c.recv_timeout(Duration::from_millis(200)), Ok(1));
+        });
+    }
+
+    #[test]
+    fn recv_timeout_times_out() {
+        let c = Channel::<i32>::new();
+        let start = Instant::now();
+        assert_eq!(c.recv_timeout(Duration::from_millis(50)), Err(RecvTimeoutError::Timeout));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn recv_timeout_doesnt_wait_once_disconnected() {
+        let (s, r) = channel::<i32>();
+        s.send(1);
+        drop(s);
+        assert_eq!(r.recv_timeout(Duration::from_secs(10)), Ok(1));
+        let start = Instant::now();
+        assert_eq!(r.recv_timeout(Duration::from_secs(10)), Err(RecvTimeoutError::Disconnected));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn recv_timeout_doesnt_start_over_on_spurious_wakeups() {
+        let c = Channel::<i32>::new();
+        let done = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                // Wakes the receiver without a message, for much longer than
+                // its timeout
+                let start = Instant::now();
+                while !done.load(Relaxed) && start.elapsed() < Duration::from_secs(2) {
+                    c.item_ready.notify_all();
+                    thread::sleep(Duration::from_millis(5));
+                }
+            });
+            let start = Instant::now();
+            assert_eq!(c.recv_timeout(Duration::from_millis(50)), Err(RecvTimeoutError::Timeout));
+            assert!(start.elapsed() < Duration::from_secs(1));
+            done.store(true, Relaxed);
+        });
+    }
+
+    #[test]
+    fn zero_sized_messages_round_trip() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Relaxed);
+            }
+        }
+        let c = Channel::new();
+        for _ in 0..3 {
+            c.send(());
+        }
+        assert_eq!(c.len(), 3);
+        assert_eq!(c.drain().len(), 3);
+        let c = Channel::new();
+        c.send(Token);
+        c.send(Token);
+        c.send(Token);
+        let token = c.receive();
+        assert_eq!(DROPS.load(Relaxed), 0);
+        drop(token);
+        assert_eq!(DROPS.load(Relaxed), 1);
+        // The ones never received are still dropped with the channel
+        drop(c);
+        assert_eq!(DROPS.load(Relaxed), 3);
+    }
+}
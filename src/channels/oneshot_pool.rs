@@ -0,0 +1,114 @@
+use std::sync::Arc;
+use crate::channels::compile_time_oneshot;
+use crate::channels::wake::WakeStrategy;
+use crate::locks::semaphore::{Semaphore, SemaphorePermit};
+
+// Hands out compile_time_oneshot channels, but no more than max_live of them
+// can be alive at the same time. acquire blocks while that many are alive,
+// until one of them is gone, so a server making one channel per request
+// can't end up allocating an unbounded number of them.
+// A channel counts as alive until both its Sender and its Receiver have been
+// dropped (or consumed by send and receive).
+pub struct OneshotPool {
+    semaphore: Semaphore,
+}
+
+// Sender and Receiver share the permit, the last one to go gives it back
+pub struct Sender<'a, T> {
+    inner: compile_time_oneshot::Sender<T>,
+    _permit: Arc<SemaphorePermit<'a>>,
+}
+
+pub struct Receiver<'a, T> {
+    inner: compile_time_oneshot::Receiver<T>,
+    _permit: Arc<SemaphorePermit<'a>>,
+}
+
+impl OneshotPool {
+    // Panics if max_live is zero, as acquire could never return
+    pub const fn new(max_live: usize) -> Self {
+        assert!(max_live > 0, "max_live must be at least 1!");
+        Self { semaphore: Semaphore::new(max_live) }
+    }
+
+    // Blocks until fewer than max_live channels from this pool are alive
+    pub fn acquire<T>(&self) -> (Sender<'_, T>, Receiver<'_, T>) {
+        let permit = Arc::new(self.semaphore.acquire());
+        let (sender, receiver) = compile_time_oneshot::channel();
+        (
+            Sender { inner: sender, _permit: permit.clone() },
+            Receiver { inner: receiver, _permit: permit },
+        )
+    }
+
+    // Same, but returns None instead of blocking
+    pub fn try_acquire<T>(&self) -> Option<(Sender<'_, T>, Receiver<'_, T>)> {
+        let permit = Arc::new(self.semaphore.try_acquire()?);
+        let (sender, receiver) = compile_time_oneshot::channel();
+        Some((
+            Sender { inner: sender, _permit: permit.clone() },
+            Receiver { inner: receiver, _permit: permit },
+        ))
+    }
+
+    // How many more channels can be acquired right now without blocking
+    pub fn available(&self) -> usize {
+        self.semaphore.available()
+    }
+}
+
+// The same interface as compile_time_oneshot, the permit just comes along
+impl<T> Sender<'_, T> {
+    pub fn send(self, message: T) {
+        self.inner.send(message);
+    }
+}
+
+impl<T> Receiver<'_, T> {
+    pub fn is_ready(&self) -> bool {
+        self.inner.is_ready()
+    }
+
+    pub fn receive(self) -> Result<T, compile_time_oneshot::RecvError> {
+        self.inner.receive()
+    }
+
+    pub fn receive_with<W: WakeStrategy>(self, strategy: W) -> Result<T, compile_time_oneshot::RecvError> {
+        self.inner.receive_with(strategy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn third_acquire_waits_for_a_channel_to_go() {
+        let pool = OneshotPool::new(2);
+        let (s1, r1) = pool.acquire::<i32>();
+        let (s2, r2) = pool.acquire::<i32>();
+        assert!(pool.try_acquire::<i32>().is_none());
+        let acquired = AtomicBool::new(false);
+        thread::scope(|s| {
+            s.spawn(|| {
+                let (s3, r3) = pool.acquire();
+                acquired.store(true, Relaxed);
+                s3.send(3);
+                assert_eq!(r3.receive(), Ok(3));
+            });
+            thread::sleep(Duration::from_millis(20));
+            assert!(!acquired.load(Relaxed));
+            // Half of a channel isn't enough
+            s1.send(1);
+            thread::sleep(Duration::from_millis(20));
+            assert!(!acquired.load(Relaxed));
+            assert_eq!(r1.receive(), Ok(1));
+        });
+        assert!(acquired.load(Relaxed));
+        drop((s2, r2));
+        assert_eq!(pool.available(), 2);
+    }
+}
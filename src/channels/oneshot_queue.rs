@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use crate::channels::safer_oneshot::{self, RecvError};
+use crate::channels::wake::ParkStrategy;
+
+// Hands out oneshot Senders, and gives the consumer their messages in the
+// order the Senders were handed out, no matter in which order they're sent.
+// Like responses to requests that finish out of order, but have to be
+// answered in the order the requests came in.
+//
+// Every Sender gets its own safer_oneshot channel, and the queue keeps them
+// in order. receive waits for the channel at the front, even if messages
+// behind it are already there.
+pub struct OneshotQueue<T> {
+    pending: Mutex<VecDeque<Arc<safer_oneshot::Channel<T>>>>,
+}
+
+// From OneshotQueue::enqueue. Dropping it without sending closes its slot,
+// so the consumer doesn't wait for it forever.
+pub struct Sender<T> {
+    channel: Arc<safer_oneshot::Channel<T>>,
+}
+
+impl<T> Default for OneshotQueue<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> OneshotQueue<T> {
+    pub const fn new() -> Self {
+        Self { pending: Mutex::new(VecDeque::new()) }
+    }
+
+    // Takes the next slot in line, the message sent on it is received after
+    // those of every Sender enqueued before it
+    pub fn enqueue(&self) -> Sender<T> {
+        let channel = Arc::new(safer_oneshot::Channel::new());
+        self.pending.lock().unwrap().push_back(channel.clone());
+        Sender { channel }
+    }
+
+    // Waits for the message of the oldest slot (parking, send wakes us up).
+    // Err(Empty) without waiting if there are no slots, Err(Closed) if the
+    // oldest slot's Sender was dropped without sending, either way the
+    // slot is gone after.
+    // Any number of threads can receive at once, each waits for its own slot.
+    pub fn receive(&self) -> Result<T, RecvError> {
+        // Not held while waiting, so senders can still enqueue
+        let channel = self.pending.lock().unwrap().pop_front().ok_or(RecvError::Empty)?;
+        channel.receive_with(ParkStrategy)
+    }
+
+    // How many slots haven't been received from yet, sent or not
+    pub fn len(&self) -> usize {
+        self.pending.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T> Sender<T> {
+    pub fn send(self, message: T) {
+        self.channel.send(message);
+    }
+}
+
+impl<T> Drop for Sender<T> {
+    fn drop(&mut self) {
+        // Does nothing if the message was sent
+        self.channel.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn received_in_enqueue_order() {
+        let q = OneshotQueue::new();
+        let senders: Vec<_> = (0..3).map(|_| q.enqueue()).collect();
+        assert_eq!(q.len(), 3);
+        thread::scope(|s| {
+            s.spawn(|| {
+                // Filled last to first
+                for (i, sender) in senders.into_iter().enumerate().rev() {
+                    sender.send(i);
+                    thread::sleep(Duration::from_millis(5));
+                }
+            });
+            assert_eq!((q.receive(), q.receive(), q.receive()), (Ok(0), Ok(1), Ok(2)));
+        });
+        assert_eq!(q.receive(), Err(RecvError::Empty));
+        assert!(q.is_empty());
+    }
+
+    #[test]
+    fn dropped_sender_closes_its_slot() {
+        let q = OneshotQueue::new();
+        let first = q.enqueue();
+        let second = q.enqueue();
+        second.send("second");
+        drop(first);
+        assert_eq!(q.receive(), Err(RecvError::Closed));
+        assert_eq!(q.receive(), Ok("second"));
+    }
+}
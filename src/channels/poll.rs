@@ -0,0 +1,116 @@
+use std::task::Poll;
+use std::time::Duration;
+use crate::channels::{mutex_based, safer_oneshot};
+
+// One way to check any channel for a message without blocking, for generic
+// code (a custom reactor, say) that has to look at all kinds of channels.
+// Pending means nothing yet, but something might still come, Ready(Err)
+// means nothing ever will.
+// Unlike a Future's poll, nothing gets woken up later, the caller has to
+// poll again to find out.
+pub trait Pollable<T> {
+    fn poll_recv(&self) -> Poll<Result<T, RecvError>>;
+}
+
+// Every sender is gone, or the channel was closed (or for
+// compile_time_oneshot, the message was already received)
+#[derive(Debug, PartialEq, Eq)]
+pub struct RecvError;
+
+// A plain Channel has no senders to lose, only one from channel() does, so
+// for those it's only Ready(Err) once it's closed, and what was queued before
+// is received
+impl<T> Pollable<T> for mutex_based::Channel<T> {
+    fn poll_recv(&self) -> Poll<Result<T, RecvError>> {
+        recv_timeout_to_poll(self.recv_timeout(Duration::ZERO))
+    }
+}
+
+impl<T> Pollable<T> for mutex_based::Receiver<T> {
+    fn poll_recv(&self) -> Poll<Result<T, RecvError>> {
+        recv_timeout_to_poll(self.recv_timeout(Duration::ZERO))
+    }
+}
+
+fn recv_timeout_to_poll<T>(result: Result<T, mutex_based::RecvTimeoutError>) -> Poll<Result<T, RecvError>> {
+    match result {
+        Ok(message) => Poll::Ready(Ok(message)),
+        Err(mutex_based::RecvTimeoutError::Timeout) => Poll::Pending,
+        Err(mutex_based::RecvTimeoutError::Disconnected) => Poll::Ready(Err(RecvError)),
+    }
+}
+
+// Only ever Ready(Ok) once. After that it's Pending forever, as the channel
+// can't tell a message that was received from one that's still being sent.
+impl<T> Pollable<T> for safer_oneshot::Channel<T> {
+    fn poll_recv(&self) -> Poll<Result<T, RecvError>> {
+        match self.try_receive() {
+            Ok(message) => Poll::Ready(Ok(message)),
+            Err(safer_oneshot::RecvError::Empty) => Poll::Pending,
+            Err(safer_oneshot::RecvError::Closed) => Poll::Ready(Err(RecvError)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::compile_time_oneshot;
+
+    // Polls every channel once, in order
+    fn poll_all<P: Pollable<i32>>(channels: &[P]) -> Vec<Poll<Result<i32, RecvError>>> {
+        channels.iter().map(|c| c.poll_recv()).collect()
+    }
+
+    #[test]
+    fn pending_then_ready_for_every_kind_of_channel() {
+        let queues = [mutex_based::Channel::new(), mutex_based::Channel::new()];
+        assert_eq!(poll_all(&queues), [Poll::Pending, Poll::Pending]);
+        queues[1].send(2);
+        assert_eq!(poll_all(&queues), [Poll::Pending, Poll::Ready(Ok(2))]);
+
+        let oneshots = [safer_oneshot::Channel::new(), safer_oneshot::Channel::new()];
+        assert_eq!(poll_all(&oneshots), [Poll::Pending, Poll::Pending]);
+        oneshots[0].send(1);
+        assert_eq!(poll_all(&oneshots), [Poll::Ready(Ok(1)), Poll::Pending]);
+    }
+
+    #[test]
+    fn split_mutex_based_is_ready_err_once_the_senders_are_gone() {
+        let (s, r) = mutex_based::channel();
+        assert_eq!(r.poll_recv(), Poll::Pending);
+        s.send(1);
+        drop(s);
+        assert_eq!(r.poll_recv(), Poll::Ready(Ok(1)));
+        assert_eq!(r.poll_recv(), Poll::Ready(Err(RecvError)));
+    }
+
+    #[test]
+    fn safer_oneshot_is_ready_err_once_closed() {
+        let c = safer_oneshot::Channel::<i32>::new();
+        c.close();
+        assert_eq!(c.poll_recv(), Poll::Ready(Err(RecvError)));
+    }
+
+    #[test]
+    fn compile_time_oneshot_is_ready_err_after_the_message() {
+        let (s, r) = compile_time_oneshot::channel();
+        assert_eq!(r.poll_recv(), Poll::Pending);
+        s.send(1);
+        assert_eq!(r.poll_recv(), Poll::Ready(Ok(1)));
+        assert_eq!(r.poll_recv(), Poll::Ready(Err(RecvError)));
+
+        let (s, r) = compile_time_oneshot::channel::<i32>();
+        drop(s);
+        assert_eq!(r.poll_recv(), Poll::Ready(Err(RecvError)));
+    }
+
+    #[test]
+    fn mutex_based_is_ready_err_once_closed() {
+        let c = mutex_based::Channel::new();
+        c.send(1);
+        c.close();
+        assert_eq!(c.poll_recv(), Poll::Ready(Ok(1)));
+        assert_eq!(c.poll_recv(), Poll::Ready(Err(RecvError)));
+    }
+}
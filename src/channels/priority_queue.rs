@@ -0,0 +1,94 @@
+use std::collections::BinaryHeap;
+use std::sync::{Condvar, Mutex};
+
+// Like mutex_based::Channel, but receive gives the greatest message that's
+// queued (by Ord) instead of the oldest one. Use std::cmp::Reverse for the
+// smallest first.
+// Unlike pushing urgent messages to the front with requeue_front, this
+// orders every message, not just two levels of them.
+// Messages that compare equal come out in no particular order.
+pub struct PriorityChannel<T: Ord> {
+    heap: Mutex<BinaryHeap<T>>,
+    item_ready: Condvar,
+}
+
+impl<T: Ord> Default for PriorityChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Ord> PriorityChannel<T> {
+    pub const fn new() -> Self {
+        Self { heap: Mutex::new(BinaryHeap::new()), item_ready: Condvar::new() }
+    }
+
+    pub fn send(&self, message: T) {
+        self.heap.lock().unwrap().push(message);
+        self.item_ready.notify_one();
+    }
+
+    // Blocks until there's a message, then takes the greatest one queued at
+    // that point
+    pub fn receive(&self) -> T {
+        let mut heap = self.heap.lock().unwrap();
+        loop {
+            // Loop in case of spurious wakeups, or another receiver taking
+            // the message before us
+            if let Some(message) = heap.pop() {
+                return message;
+            }
+            heap = self.item_ready.wait(heap).unwrap();
+        }
+    }
+
+    pub fn try_receive(&self) -> Option<T> {
+        self.heap.lock().unwrap().pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn greatest_first() {
+        let c = PriorityChannel::new();
+        for i in [3, 1, 4, 1, 5] {
+            c.send(i);
+        }
+        assert_eq!([c.receive(), c.receive(), c.receive(), c.receive(), c.receive()], [5, 4, 3, 1, 1]);
+        assert_eq!(c.try_receive(), None);
+        let c = PriorityChannel::new();
+        c.send(Reverse(2));
+        c.send(Reverse(1));
+        assert_eq!(c.receive(), Reverse(1));
+    }
+
+    #[test]
+    fn later_higher_priority_message_comes_first() {
+        let c = PriorityChannel::new();
+        c.send(1);
+        c.send(2);
+        // Sent after the others, but before anything's received
+        c.send(10);
+        assert_eq!(c.receive(), 10);
+        assert_eq!(c.receive(), 2);
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn receive_waits_for_a_message() {
+        let c = PriorityChannel::new();
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive());
+            thread::sleep(Duration::from_millis(10));
+            assert!(!h.is_finished());
+            c.send(7);
+            assert_eq!(h.join().unwrap(), 7);
+        });
+    }
+}
@@ -0,0 +1,105 @@
+use std::sync::{Condvar, Mutex};
+use std::thread::{self, Scope};
+
+// An exchanger: two threads meet, and each hands the other a value.
+// Whoever calls exchange first waits for a partner, the partner takes the
+// first one's value and leaves its own, and both go on with the other's value.
+// Any number of threads can use the same Exchanger, they're paired up in the
+// order they get the lock.
+pub struct Exchanger<T> {
+    state: Mutex<State<T>>,
+    changed: Condvar,
+}
+
+struct State<T> {
+    // The value of a thread waiting for a partner
+    offered: Option<T>,
+    // The partner's value, until the waiting thread picks it up. While this
+    // is set, the next pair can't start, or the reply could go to the wrong
+    // thread.
+    reply: Option<T>,
+}
+
+// Sets up an Exchanger and a thread::scope, and gives both to f, so f can
+// spawn the threads that exchange values, and they're all joined before this
+// returns.
+pub fn exchange<T, F>(f: F)
+where
+    T: Send,
+    F: for<'scope, 'env> FnOnce(&'scope Scope<'scope, 'env>, &'scope Exchanger<T>),
+{
+    let exchanger = Exchanger::new();
+    thread::scope(|s| f(s, &exchanger));
+}
+
+impl<T> Default for Exchanger<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Exchanger<T> {
+    pub const fn new() -> Self {
+        Self {
+            state: Mutex::new(State { offered: None, reply: None }),
+            changed: Condvar::new(),
+        }
+    }
+
+    // Blocks until another thread calls exchange too, then returns its value
+    pub fn exchange(&self, value: T) -> T {
+        let mut state = self.state.lock().unwrap();
+        // The previous pair isn't done yet
+        while state.reply.is_some() {
+            state = self.changed.wait(state).unwrap();
+        }
+        if let Some(theirs) = state.offered.take() {
+            // Someone's waiting for us
+            state.reply = Some(value);
+            drop(state);
+            self.changed.notify_all();
+            return theirs;
+        }
+        state.offered = Some(value);
+        // Loop in case of spurious wakeups. Only our partner sets reply while
+        // we're waiting, the next pair waits for us to take it first.
+        loop {
+            if let Some(theirs) = state.reply.take() {
+                drop(state);
+                // There might be threads waiting for the next pair to start
+                self.changed.notify_all();
+                return theirs;
+            }
+            state = self.changed.wait(state).unwrap();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_threads_swap_values() {
+        exchange(|s, exchanger| {
+            let a = s.spawn(|| exchanger.exchange("from a"));
+            let b = s.spawn(|| exchanger.exchange("from b"));
+            assert_eq!(a.join().unwrap(), "from b");
+            assert_eq!(b.join().unwrap(), "from a");
+        });
+    }
+
+    #[test]
+    fn threads_are_paired_up() {
+        let mut received = Vec::new();
+        exchange(|s, exchanger| {
+            let handles: Vec<_> = (0..8).map(|i| s.spawn(move || (i, exchanger.exchange(i)))).collect();
+            received = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        });
+        // Nobody gets their own value back, and a partner gets ours
+        for &(mine, theirs) in &received {
+            assert_ne!(mine, theirs);
+            assert!(received.contains(&(theirs, mine)));
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Release};
+
+// A single producer, single consumer ring buffer of N slots, without locks or
+// allocation. Like borrowing_oneshot, the user owns the Channel, and split
+// hands out a Producer and a Consumer borrowing it, so there can only ever be
+// one of each at a time.
+//
+// head is the position of the next message to read, tail the position of the
+// next slot to write. Both only ever count up (wrapping around), the slot for
+// a position is at position % N. The ring is empty when they're equal, and
+// full when tail is N ahead of head.
+// Only the consumer writes head, and only the producer writes tail.
+//
+// Each side keeps its own position in a plain field, as it's the only one
+// writing it, and a cached copy of the other side's position, which it only
+// reads the atomic for again when the cached copy says the ring is full (for
+// the producer) or empty (for the consumer). The cache can only ever be
+// behind, which only ever makes the ring look fuller (or emptier) than it is,
+// never the other way round, so that's safe. While there's plenty of room (or
+// plenty queued), a push or pop doesn't touch the other side's cache line at
+// all, instead of reading it every time. With a steady stream of messages,
+// that's about one read of the other side's position per lap around the
+// ring, instead of one per message.
+pub struct Channel<T, const N: usize> {
+    slots: [UnsafeCell<MaybeUninit<T>>; N],
+    // In separate cache lines, so the producer writing tail doesn't keep
+    // invalidating the cache line the consumer writes head to (false sharing)
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+// Also used by spsc_ring
+#[repr(align(64))]
+pub(crate) struct CachePadded<T>(pub(crate) T);
+
+// Messages only ever go from the producer's thread to the consumer's
+unsafe impl<T, const N: usize> Sync for Channel<T, N> where T: Send {}
+
+pub struct Producer<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+    tail: usize,
+    cached_head: usize,
+}
+
+pub struct Consumer<'a, T, const N: usize> {
+    channel: &'a Channel<T, N>,
+    head: usize,
+    cached_tail: usize,
+}
+
+// Anything between head and tail was pushed but never popped
+impl<T, const N: usize> Drop for Channel<T, N> {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        while head != tail {
+            unsafe { self.slots[head % N].get_mut().assume_init_drop() }
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+impl<T, const N: usize> Default for Channel<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Channel<T, N> {
+    const NOT_EMPTY: () = assert!(N > 0, "A ring buffer needs at least 1 slot!");
+
+    pub const fn new() -> Self {
+        let () = Self::NOT_EMPTY;
+        Self {
+            slots: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: CachePadded(AtomicUsize::new(0)),
+            tail: CachePadded(AtomicUsize::new(0)),
+        }
+    }
+
+    // A channel that starts out holding the first N items from iter, as if
+    // they had been pushed in that order.
+    // Anything past N is left in the iterator, it isn't even taken out of it.
+    pub fn from_iter_prefilled(iter: impl IntoIterator<Item = T>) -> Self {
+        let mut channel = Self::new();
+        // The slots first, so zip stops before taking an item with no slot to
+        // go in. tail goes up with every item, so if iter panics, Drop still
+        // drops the ones already in.
+        for (slot, item) in channel.slots.iter_mut().zip(iter) {
+            slot.get_mut().write(item);
+            *channel.tail.0.get_mut() += 1;
+        }
+        channel
+    }
+
+    // Exclusively borrowing self means no other Producer or Consumer can
+    // exist at the same time. Whatever is still queued from a previous split
+    // stays queued.
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        let head = *self.head.0.get_mut();
+        let tail = *self.tail.0.get_mut();
+        let channel = &*self;
+        (
+            Producer { channel, tail, cached_head: head },
+            Consumer { channel, head, cached_tail: tail },
+        )
+    }
+
+    pub fn len(&mut self) -> usize {
+        self.tail.0.get_mut().wrapping_sub(*self.head.0.get_mut())
+    }
+
+    pub fn is_empty(&mut self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Gives the message back if the ring is full
+    pub fn push(&mut self, message: T) -> Result<(), T> {
+        if self.tail.wrapping_sub(self.cached_head) == N {
+            // Looks full, but the consumer might have made room since we last
+            // looked. Acquire, so the consumer is done reading the slot we're
+            // about to write.
+            self.cached_head = self.channel.head.0.load(Acquire);
+            if self.tail.wrapping_sub(self.cached_head) == N {
+                return Err(message);
+            }
+        }
+        // Safety: The slot is between head and tail + N, so it's empty, and
+        // only we write to slots
+        unsafe { (*self.channel.slots[self.tail % N].get()).write(message); }
+        self.tail = self.tail.wrapping_add(1);
+        // Release the message to the consumer
+        self.channel.tail.0.store(self.tail, Release);
+        Ok(())
+    }
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    /// Returns None if the ring is empty
+    pub fn pop(&mut self) -> Option<T> {
+        if self.head == self.cached_tail {
+            // Looks empty, but the producer might have pushed since we last
+            // looked. Acquire, pairs with the Release in push.
+            self.cached_tail = self.channel.tail.0.load(Acquire);
+            if self.head == self.cached_tail {
+                return None;
+            }
+        }
+        // Safety: The slot is between head and tail, so it holds a message,
+        // and only we read from slots
+        let message = unsafe { (*self.channel.slots[self.head % N].get()).assume_init_read() };
+        self.head = self.head.wrapping_add(1);
+        // Release, so we're done reading before the producer reuses the slot
+        self.channel.head.0.store(self.head, Release);
+        Some(message)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prefilled_with_fewer_than_n() {
+        let mut c = Channel::<_, 4>::from_iter_prefilled([1, 2]);
+        assert_eq!(c.len(), 2);
+        let (mut p, mut q) = c.split();
+        assert_eq!((q.pop(), q.pop(), q.pop()), (Some(1), Some(2), None));
+        // The rest of the slots are free
+        for i in 3..7 {
+            p.push(i).unwrap();
+        }
+        assert_eq!(p.push(7), Err(7));
+    }
+
+    #[test]
+    fn prefilled_with_exactly_n() {
+        let mut c = Channel::<_, 3>::from_iter_prefilled(1..=3);
+        assert_eq!(c.len(), 3);
+        let (mut p, mut q) = c.split();
+        assert_eq!(p.push(4), Err(4));
+        assert_eq!((q.pop(), q.pop(), q.pop(), q.pop()), (Some(1), Some(2), Some(3), None));
+    }
+
+    #[test]
+    fn prefilled_with_more_than_n() {
+        let mut items = 1..=5;
+        let mut c = Channel::<_, 3>::from_iter_prefilled(&mut items);
+        assert_eq!(c.len(), 3);
+        // The excess stays in the iterator
+        assert_eq!(items.next(), Some(4));
+        let (_p, mut q) = c.split();
+        assert_eq!((q.pop(), q.pop(), q.pop(), q.pop()), (Some(1), Some(2), Some(3), None));
+    }
+
+    #[test]
+    fn prefilled_messages_are_dropped_with_the_channel() {
+        let a = std::sync::Arc::new(());
+        drop(Channel::<_, 2>::from_iter_prefilled([a.clone(), a.clone()]));
+        assert_eq!(std::sync::Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn messages_arrive_in_order() {
+        const MESSAGES: usize = if cfg!(miri) { 500 } else { 1_000_000 };
+        // Small, so the producer keeps running into a full ring, and both
+        // sides keep having to refresh their cached index. Yielding rather
+        // than spinning while waiting, so this doesn't crawl on a single core.
+        let mut c = Channel::<usize, 8>::new();
+        let (mut p, mut q) = c.split();
+        std::thread::scope(|s| {
+            s.spawn(move || {
+                for i in 0..MESSAGES {
+                    let mut m = i;
+                    while let Err(back) = p.push(m) {
+                        m = back;
+                        std::thread::yield_now();
+                    }
+                }
+            });
+            for i in 0..MESSAGES {
+                let m = loop {
+                    if let Some(m) = q.pop() {
+                        break m;
+                    }
+                    std::thread::yield_now();
+                };
+                assert_eq!(m, i);
+            }
+            assert_eq!(q.pop(), None);
+        });
+    }
+
+    #[test]
+    fn split_again_keeps_whats_queued() {
+        let mut c = Channel::<_, 2>::new();
+        let (mut p, _) = c.split();
+        p.push(1).unwrap();
+        let (mut p, mut q) = c.split();
+        p.push(2).unwrap();
+        assert_eq!(p.push(3), Err(3));
+        assert_eq!((q.pop(), q.pop()), (Some(1), Some(2)));
+    }
+}
@@ -1,13 +1,71 @@
-use std::cell::UnsafeCell;
-use std::mem::MaybeUninit;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+// Without the std feature, only what needs nothing but core is left: send,
+// receive and the rest of the non-blocking API, and SendHandle. on_ready
+// needs std's Mutex, and waiting in receive_with needs std's threads.
+use core::cell::UnsafeCell;
+use core::mem::{self, MaybeUninit};
+#[cfg(feature = "std")]
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize};
+use core::sync::atomic::Ordering::{self, AcqRel, Acquire, Relaxed, Release};
+#[cfg(feature = "std")]
+use core::sync::atomic::{fence, AtomicPtr, Ordering::SeqCst};
+#[cfg(feature = "std")]
+use std::sync::{Mutex, MutexGuard};
+#[cfg(feature = "std")]
+use std::thread::{self, Thread};
+use crate::channels::leak_check::Tracker;
+#[cfg(feature = "std")]
+use crate::channels::wake::{self, WakeStrategy};
+#[cfg(feature = "std")]
+use crate::locks::event::Event;
 
 // Typical use case: sending only one message from one thread to another
 pub struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
     in_use: AtomicBool,
-    ready: AtomicBool,
+    // READY, SENT and CALLBACK, see there
+    state: AtomicU8,
+    // Set by close, when the sender decides it won't send after all
+    closed: AtomicBool,
+    // The thread waiting in receive_with, if any, so send and close can wake
+    // it up. Points to a Box<Thread>, whoever swaps it out owns the Box.
+    #[cfg(feature = "std")]
+    waiter: AtomicPtr<Thread>,
+    // The callback from on_ready, see there
+    #[cfg(feature = "std")]
+    on_ready: Mutex<OnReady<T>>,
+    tracker: Tracker,
+}
+
+// The message is there to be received. Reset by whoever receives it.
+const READY: u8 = 1;
+// The message was published, stays set after it's received, so on_ready
+// can tell a message that's already gone from one that's still coming
+const SENT: u8 = 2;
+// on_ready was called, so from now on, sends and receives take the on_ready
+// lock, to stay in step with the callback. Without it, they're a single
+// atomic operation on state, like they always were. Only there with std.
+#[cfg(feature = "std")]
+const CALLBACK: u8 = 4;
+
+#[cfg(feature = "std")]
+enum OnReady<T> {
+    // No callback
+    Waiting,
+    Registered(Box<dyn FnOnce(&T) + Send>),
+    // The sender took the callback out and is calling it, READY isn't set yet
+    Calling,
+    // The sender called the callback, and published the message
+    Called,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum RecvError {
+    // No message has been sent yet
+    Empty,
+    // The channel was closed without a message being sent, so no message
+    // will ever arrive
+    Closed,
 }
 
 // Tell compiler our channel is safe to share between threads, as long as
@@ -20,45 +78,290 @@ unsafe impl<T> Sync for Channel<T> where T: Send {}
 /// reference, meeting that requirement
 impl<T> Drop for Channel<T> {
     fn drop(&mut self) {
-        if *self.ready.get_mut() {
+        if *self.state.get_mut() & READY != 0 {
             unsafe { self.message.get_mut().assume_init_drop() }
+            self.tracker.message_gone();
+        }
+        #[cfg(feature = "std")]
+        {
+            let waiter = *self.waiter.get_mut();
+            if !waiter.is_null() {
+                drop(unsafe { Box::from_raw(waiter) });
+            }
         }
     }
 }
 
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Channel<T> {
-    // A new channel is empty, with message being uninitialized and ready set
-    // to false
+    // A new channel is empty, with message being uninitialized and nothing
+    // set in state
     pub const fn new() -> Self {
         Self {
             message: UnsafeCell::new(MaybeUninit::uninit()),
             in_use: AtomicBool::new(false),
-            ready: AtomicBool::new(false),
+            state: AtomicU8::new(0),
+            closed: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            waiter: AtomicPtr::new(ptr::null_mut()),
+            #[cfg(feature = "std")]
+            on_ready: Mutex::new(OnReady::Waiting),
+            tracker: Tracker::new(),
         }
     }
 
-    /// Panics when trying to send more than one message
-    pub fn send(&self, message: T) {
-        if self.in_use.swap(true, Relaxed) {
-            panic!("Can't send more than one message!");
+    // A channel that already holds a message, as if send had been called on
+    // it, so receive succeeds right away and any further send panics.
+    // If the message is never received, Drop takes care of it, as READY is set.
+    pub fn ready_with(message: T) -> Self {
+        let tracker = Tracker::new();
+        tracker.message_sent();
+        Self {
+            message: UnsafeCell::new(MaybeUninit::new(message)),
+            in_use: AtomicBool::new(true),
+            state: AtomicU8::new(READY | SENT),
+            closed: AtomicBool::new(false),
+            #[cfg(feature = "std")]
+            waiter: AtomicPtr::new(ptr::null_mut()),
+            #[cfg(feature = "std")]
+            on_ready: Mutex::new(OnReady::Waiting),
+            tracker,
         }
-        // Safety: We've checked and reset the in_use flag with swap
+    }
+
+    /// Panics when trying to send more than one message, or when sending on
+    /// a closed channel
+    pub fn send(&self, message: T) {
+        self.claim();
+        // Safety: We've checked and set the in_use flag with swap in claim.
         // Basically once we do that swap, we panic if it's called again
         // from anywhere, whether that be another thread or not, this ensures
         // that once this send starts another cannot occur, because only a
-        // single swap can occur, as in_use flag is never reset to false,
-        // we can use relaxed ordering above.
+        // single swap can occur, until in_use is reset to false by
+        // SendHandle::abort.
+        //
+        // Nothing between claiming the channel and publishing the message can
+        // panic: the message is moved in, and a move is a plain copy of its
+        // bytes, it never runs any of the message's code. Anything that could
+        // panic while making the message (its constructor, say) runs before
+        // send is called, with the channel still untouched. So there's no way
+        // to unwind out of here with the channel claimed but empty, and no
+        // guard is needed to give it back.
         unsafe { (*self.message.get()).write(message); }
-        self.ready.store(true, Release);
+        self.publish();
     }
 
-    // We're not going to make a blocking interface, it'll be up to the user to
-    // use something like thread parking if they want to block.
+    /// The first half of a send: claims the channel and puts the message in
+    /// it, but doesn't make it receivable yet. The handle either commits the
+    /// message, finishing the send, or aborts it, taking the message back
+    /// and leaving the channel as if nothing had been sent. Dropping the
+    /// handle aborts.
+    ///
+    /// Panics like send
+    pub fn begin_send(&self, message: T) -> SendHandle<'_, T> {
+        self.claim();
+        // Safety: Same as in send
+        unsafe { (*self.message.get()).write(message); }
+        SendHandle { channel: self }
+    }
+
+    // Acquire, as in_use can be reset by SendHandle::abort, which read the
+    // message slot before releasing it, so that read is done before we write.
+    fn claim(&self) {
+        if self.in_use.swap(true, Acquire) {
+            if self.is_closed() {
+                panic!("Can't send on a closed channel!");
+            }
+            panic!("Can't send more than one message!");
+        }
+    }
+
+    // Makes the message we've written receivable
+    fn publish(&self) {
+        self.tracker.message_sent();
+        self.make_ready();
+    }
+
+    // Sets READY and SENT, unless on_ready was called, then it's the slow
+    // path below
+    fn make_ready(&self) {
+        if self.state.compare_exchange(0, READY | SENT, Release, Relaxed).is_err() {
+            #[cfg(feature = "std")]
+            self.make_ready_with_callback();
+        }
+        #[cfg(feature = "std")]
+        self.wake_waiter();
+    }
+
+    // Takes the on_ready callback out, if there is one, calls it, then sets
+    // READY. on_ready either registers before this and gets called here, or
+    // sees Calling, or READY after this. The callback runs before READY is
+    // set, so no receiver can take the message from under it.
+    // The callback is called without holding the lock, so it can use the
+    // channel itself (calling on_ready again panics, like any second
+    // registration). The state and READY are set together under the lock, so
+    // on_ready never sees Called without the message being receivable.
+    // If the callback panics, the guard still publishes the message, so it
+    // isn't lost.
+    #[cfg(feature = "std")]
+    #[cold]
+    fn make_ready_with_callback(&self) {
+        struct SetReady<'a, T>(&'a Channel<T>, OnReady<T>);
+        impl<T> Drop for SetReady<'_, T> {
+            fn drop(&mut self) {
+                let mut state = self.0.lock_on_ready();
+                *state = mem::replace(&mut self.1, OnReady::Waiting);
+                self.0.state.fetch_or(READY | SENT, Release);
+            }
+        }
+        let mut state = self.lock_on_ready();
+        // Calling only if there's a callback to call. Without one, on_ready
+        // only looked for a message that wasn't there yet, so just publish.
+        let f = match mem::replace(&mut *state, OnReady::Calling) {
+            OnReady::Registered(f) => f,
+            other => {
+                *state = other;
+                self.state.fetch_or(READY | SENT, Release);
+                return;
+            }
+        };
+        drop(state);
+        let guard = SetReady(self, OnReady::Called);
+        // Safety: The message was written, and READY isn't set yet, so no
+        // receiver can touch it
+        f(unsafe { (*self.message.get()).assume_init_ref() });
+        drop(guard);
+    }
+
+    // Resets READY to claim the message for a receive. Once on_ready was
+    // called, under the on_ready lock, so it waits for an on_ready that's
+    // still looking at the message. The compare_exchange fails if on_ready
+    // sets CALLBACK in the meantime, so then we take the lock too.
+    fn claim_message(&self) -> bool {
+        let mut state = self.state.load(Relaxed);
+        loop {
+            if state & READY == 0 {
+                return false;
+            }
+            #[cfg(feature = "std")]
+            if state & CALLBACK != 0 {
+                let _state = self.lock_on_ready();
+                return self.state.fetch_and(!READY, Acquire) & READY != 0;
+            }
+            match self.state.compare_exchange_weak(state, state & !READY, Acquire, Relaxed) {
+                Ok(_) => return true,
+                Err(now) => state = now,
+            }
+        }
+    }
+
+    // A panicking callback poisons the lock, but the state is still fine
+    #[cfg(feature = "std")]
+    fn lock_on_ready(&self) -> MutexGuard<'_, OnReady<T>> {
+        self.on_ready.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Registers f to be called with the message when it's sent, by the
+    /// thread sending it, before any receiver can take it.
+    /// If the message was already sent, f is called right away, on this thread,
+    /// as long as it hasn't been received yet. Once it's been received, there's
+    /// nothing to call f with, and f is dropped without being called.
+    /// f is called right away while holding a lock that keeps receivers from
+    /// taking the message, so a receive on another thread waits for f to
+    /// finish, and f itself must not receive from this channel.
+    ///
+    /// Panics if a callback was already registered, only one is allowed
+    #[cfg(feature = "std")]
+    pub fn on_ready(&self, f: impl FnOnce(&T) + Send + 'static) {
+        let mut state = self.lock_on_ready();
+        if matches!(*state, OnReady::Registered(_) | OnReady::Calling) {
+            panic!("Only one on_ready callback allowed!");
+        }
+        // Sends and receives take the lock we hold from now on. Acquire, for
+        // the message a send published before this
+        let bits = self.state.fetch_or(CALLBACK, Acquire);
+        if bits & READY != 0 {
+            // Receivers claim the message under the lock we hold, so it stays
+            // put while f borrows it.
+            // Safety: READY is set, so the message is initialized
+            f(unsafe { (*self.message.get()).assume_init_ref() });
+        } else if bits & SENT == 0 {
+            // Not sent yet, send sees CALLBACK and calls f
+            *state = OnReady::Registered(Box::new(f));
+        }
+    }
+
+    // For a sender that decides not to send after all, tells the receiver that
+    // no message is coming instead of leaving it waiting forever. A receiver
+    // waiting in receive_with is woken up and gets RecvError::Closed.
+    // Uses up the channel the same way send does, so sending afterwards panics.
+    // Does nothing if a message was already sent.
+    pub fn close(&self) {
+        if self.in_use.swap(true, Relaxed) {
+            return;
+        }
+        self.closed.store(true, Relaxed);
+        #[cfg(feature = "std")]
+        self.wake_waiter();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Relaxed)
+    }
+
+    // receive itself doesn't block, it's up to the user to wait until
+    // is_ready, or to use receive_with to pick how to wait.
 
     /// We can lower the memory ordering of load to Relaxed, since it's now
     /// only used for indicative purposes.
     pub fn is_ready(&self) -> bool {
-        self.ready.load(Relaxed)
+        self.state.load(Relaxed) & READY != 0
+    }
+
+    /// Like is_ready, but with a memory ordering of the caller's choosing, for
+    /// protocols built around the channel that need is_ready to synchronize.
+    /// With Acquire (or SeqCst), seeing true means everything the sender did
+    /// before send is visible, even without receiving the message.
+    ///
+    /// Panics if ordering is Release or AcqRel, as those aren't valid for a
+    /// load
+    pub fn is_ready_with(&self, ordering: Ordering) -> bool {
+        if matches!(ordering, Release | AcqRel) {
+            panic!("Invalid ordering for a load: {ordering:?}");
+        }
+        self.state.load(ordering) & READY != 0
+    }
+
+    /// is_ready_with(Acquire), for the common case. Use this when seeing
+    /// true has to mean the sender's writes before send are visible, to then
+    /// read something the sender wrote (other than the message) without any
+    /// more synchronization. Plain is_ready is enough when it's just to
+    /// decide whether to call receive, which synchronizes by itself.
+    pub fn is_ready_acquire(&self) -> bool {
+        self.state.load(Acquire) & READY != 0
+    }
+
+    /// Shows off the happens-before relationship send sets up, for playing
+    /// with the orderings: prior starts out as 0, and the sending thread
+    /// stores something else in it before send. If this sees ready, it's
+    /// guaranteed to see that store too, even with a Relaxed load of prior,
+    /// because the Acquire load of ready synchronizes with the Release store
+    /// of ready in send, and the store to prior came before that.
+    ///
+    /// Returns whether prior's new value was seen, so false if the message
+    /// wasn't sent yet (or was already received, which resets ready).
+    /// With a Relaxed load of ready instead, true would only be likely, not
+    /// guaranteed, when ready was seen.
+    pub fn observe_after(&self, prior: &AtomicUsize) -> bool {
+        if self.state.load(Acquire) & READY == 0 {
+            return false;
+        }
+        prior.load(Relaxed) != 0
     }
 
     /// Panics if no message is available yet,
@@ -75,10 +378,634 @@ impl<T> Channel<T> {
     /// Still unsafe, as user still responsible for not calling this more than
     /// once
     pub fn receive(&self) -> T {
-        if !self.ready.swap(false, Acquire) {
-            panic!("No message available!");
+        match self.try_receive() {
+            Ok(message) => message,
+            Err(RecvError::Empty) => panic!("No message available!"),
+            Err(RecvError::Closed) => panic!("Channel was closed!"),
+        }
+    }
+
+    /// Like receive, but gives an error instead of panicking when there's
+    /// no message
+    pub fn try_receive(&self) -> Result<T, RecvError> {
+        if !self.claim_message() {
+            if self.is_closed() {
+                return Err(RecvError::Closed);
+            }
+            return Err(RecvError::Empty);
         }
+        self.tracker.message_gone();
         // Safety: We've just checked (and reset) the ready flag with swap call
-        unsafe { (*self.message.get()).assume_init_read() }
+        Ok(unsafe { (*self.message.get()).assume_init_read() })
+    }
+
+    /// Only receives the message if pred returns true for it, otherwise the
+    /// message stays in the channel and can still be received later.
+    /// Returns None if there's no message, or pred returned false.
+    pub fn receive_if(&self, pred: impl FnOnce(&T) -> bool) -> Option<T> {
+        // Claim the message while pred looks at it, so only this one call
+        // can be reading it
+        if !self.claim_message() {
+            return None;
+        }
+        // Puts the message back if pred says no, or if pred panics, so the
+        // message isn't lost (and is still dropped by Drop)
+        struct Restore<'a>(&'a AtomicU8);
+        impl Drop for Restore<'_> {
+            fn drop(&mut self) {
+                self.0.fetch_or(READY, Release);
+            }
+        }
+        let restore = Restore(&self.state);
+        // Safety: ready was set, so the message is initialized, and we've
+        // claimed it, so no one else reads it while we borrow it
+        if !pred(unsafe { (*self.message.get()).assume_init_ref() }) {
+            return None;
+        }
+        mem::forget(restore);
+        self.tracker.message_gone();
+        // Safety: Same as in try_receive, we've reset the ready flag
+        Some(unsafe { (*self.message.get()).assume_init_read() })
+    }
+
+    /// A copy of the message, without receiving it, so ready stays set and
+    /// receive still gets the message afterwards. None if there's no message
+    /// (or it was already received).
+    /// Only for Copy messages: a copy of anything else would be a second
+    /// owner of whatever the message owns.
+    pub fn peek(&self) -> Option<T>
+    where
+        T: Copy,
+    {
+        // Acquire pairs with the Release in send, like in try_receive
+        if self.state.load(Acquire) & READY == 0 {
+            return None;
+        }
+        // Safety: ready was set, so the message is initialized, and once sent
+        // it's never written again. A receive at the same time only reads it
+        // too, and a Copy message has no Drop.
+        Some(unsafe { (*self.message.get()).assume_init_read() })
+    }
+
+    /// Sets event once the message is sent, so any number of threads can wait
+    /// for it on the event, without any of them taking the message. Goes
+    /// through on_ready, so it can't be combined with another on_ready
+    /// callback, and panics like it if there already is one.
+    /// If the message was already sent, event is set right away, unless it was
+    /// also received already, then event is left alone.
+    #[cfg(feature = "std")]
+    pub fn set_event_on_ready(&self, event: &Event) {
+        let event = event.clone();
+        self.on_ready(move |_| event.set());
+    }
+
+    /// Waits for the message using the given strategy, then receives it.
+    /// With ParkStrategy there's no need for the sender to unpark this
+    /// thread, send and close do that.
+    ///
+    /// Only gives an error if the channel was closed.
+    /// Panics if the message was already consumed
+    #[cfg(feature = "std")]
+    pub fn receive_with<W: WakeStrategy>(&self, strategy: W) -> Result<T, RecvError> {
+        self.register_waiter();
+        wake::wait_until(&strategy, || self.is_ready() || self.is_closed());
+        if self.is_closed() {
+            return Err(RecvError::Closed);
+        }
+        Ok(self.receive())
+    }
+
+    /// Parks the current thread until the message arrives, then receives it,
+    /// so there's no need for a park loop, or for the sender to unpark us.
+    ///
+    /// Only one thread may wait on a channel at a time, the designated
+    /// receiver. A second waiting thread replaces the first one's
+    /// registration, and the first one might never be woken up.
+    ///
+    /// Panics if the channel was closed, or the message was already consumed
+    #[cfg(feature = "std")]
+    pub fn receive_blocking(&self) -> T {
+        match self.receive_with(wake::ParkStrategy) {
+            Ok(message) => message,
+            Err(_) => panic!("Channel was closed!"),
+        }
+    }
+
+    // Registers the current thread to be unparked by send or close.
+    // The fence here and the one in wake_waiter make sure that either we see
+    // ready (or closed) after registering, or the sender sees our registration
+    // after setting ready (or closed), so a wakeup can't get lost.
+    // Also used by select::select2, to wait on two channels at once.
+    #[cfg(feature = "std")]
+    pub(crate) fn register_waiter(&self) {
+        let new = Box::into_raw(Box::new(thread::current()));
+        let old = self.waiter.swap(new, AcqRel);
+        if !old.is_null() {
+            drop(unsafe { Box::from_raw(old) });
+        }
+        fence(SeqCst);
     }
-}
\ No newline at end of file
+
+    #[cfg(feature = "std")]
+    fn wake_waiter(&self) {
+        fence(SeqCst);
+        let waiter = self.waiter.swap(ptr::null_mut(), AcqRel);
+        if !waiter.is_null() {
+            // Safety: We swapped it out, so we own the Box now
+            let thread = unsafe { Box::from_raw(waiter) };
+            wake::unpark(&thread);
+        }
+    }
+}
+
+// A message that's in the channel, but not receivable until it's committed,
+// from Channel::begin_send
+pub struct SendHandle<'a, T> {
+    channel: &'a Channel<T>,
+}
+
+impl<T> SendHandle<'_, T> {
+    pub fn commit(self) {
+        self.channel.publish();
+        mem::forget(self);
+    }
+
+    // Gives the message back, the channel can be sent on again afterwards
+    pub fn abort(self) -> T {
+        let message = self.take_back();
+        mem::forget(self);
+        message
+    }
+
+    // Only called once, by abort or Drop, as both use up the handle
+    fn take_back(&self) -> T {
+        // Safety: We wrote the message in begin_send, and ready was never set,
+        // so no one else can have read it
+        let message = unsafe { (*self.channel.message.get()).assume_init_read() };
+        // Release, so we're done reading before the next send writes, see claim
+        self.channel.in_use.store(false, Release);
+        message
+    }
+}
+
+impl<T> Drop for SendHandle<'_, T> {
+    fn drop(&mut self) {
+        drop(self.take_back());
+    }
+}
+
+// Used by transfer::relay, it needs to move the message between the two
+// channels' slots directly, which it can't do through send and receive.
+#[cfg(feature = "std")]
+impl<T> Channel<T> {
+    pub(super) fn relay_to(&self, to: &Channel<T>) -> bool {
+        if !self.claim_message() {
+            return false;
+        }
+        // Acquire, like claim, as an aborted SendHandle on to might have
+        // just read its message out of the slot we're about to write
+        if to.in_use.swap(true, Acquire) {
+            // Put the message back where we found it before panicking, so it's
+            // still receivable from self (and dropped by self's Drop otherwise)
+            self.state.fetch_or(READY, Release);
+            panic!("Can't send more than one message!");
+        }
+        // Safety: We've claimed the message in self by resetting ready, and the
+        // slot in to by setting in_use. The message is copied bit for bit and
+        // self's slot is treated as uninitialized from here on, nothing can
+        // panic in between, so there's no owned copy that could be dropped twice
+        // or leaked.
+        unsafe {
+            ptr::copy_nonoverlapping(self.message.get(), to.message.get(), 1);
+        }
+        self.tracker.hand_over(&to.tracker);
+        to.make_ready();
+        true
+    }
+}
+
+// Most of them use the parts that need std, on_ready and receive_with
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use std::sync::Arc;
+
+    #[test]
+    fn ready_with_receives_right_away() {
+        let c = Channel::ready_with("hi");
+        assert!(c.is_ready());
+        assert_eq!(c.receive(), "hi");
+    }
+
+    #[test]
+    fn ready_with_drops_an_unreceived_message() {
+        let a = Arc::new(());
+        drop(Channel::ready_with(a.clone()));
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    fn ready_with_cant_be_sent_to() {
+        let c = Channel::ready_with(1);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.send(2))).is_err());
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn close_wakes_a_waiting_receiver() {
+        let c = Channel::<i32>::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(std::time::Duration::from_millis(20));
+                c.close();
+            });
+            // Nothing unparks us but close
+            assert_eq!(c.receive_with(wake::ParkStrategy), Err(RecvError::Closed));
+        });
+        assert!(c.is_closed());
+        assert_eq!(c.try_receive(), Err(RecvError::Closed));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.send(1))).is_err());
+    }
+
+    #[test]
+    fn close_after_send_does_nothing() {
+        let c = Channel::new();
+        assert_eq!(c.try_receive(), Err(RecvError::Empty));
+        c.send(1);
+        c.close();
+        assert!(!c.is_closed());
+        assert_eq!(c.receive_with(wake::ParkStrategy), Ok(1));
+    }
+
+    #[test]
+    fn receive_if_rejected_stays_receivable() {
+        let c = Channel::new();
+        assert_eq!(c.receive_if(|_| true), None);
+        c.send(5);
+        assert_eq!(c.receive_if(|m| *m > 10), None);
+        assert!(c.is_ready());
+        assert_eq!(c.receive(), 5);
+    }
+
+    #[test]
+    fn receive_if_accepted_is_consumed() {
+        let c = Channel::new();
+        c.send(String::from("take me"));
+        assert_eq!(c.receive_if(|m| m.starts_with("take")).as_deref(), Some("take me"));
+        assert!(!c.is_ready());
+        assert_eq!(c.try_receive(), Err(RecvError::Empty));
+    }
+
+    #[test]
+    fn receive_if_panicking_pred_keeps_the_message() {
+        let c = Channel::new();
+        c.send(1);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| c.receive_if(|_| panic!("pred"))));
+        assert!(result.is_err());
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn is_ready_acquire_makes_earlier_writes_visible() {
+        // A plain, non-atomic value the sender writes before sending, read
+        // after seeing is_ready without any other synchronization. Under
+        // miri, this is reported as a data race with a Relaxed load instead.
+        struct Unsynced(UnsafeCell<u64>);
+        unsafe impl Sync for Unsynced {}
+        impl Unsynced {
+            fn get(&self) -> *mut u64 {
+                self.0.get()
+            }
+        }
+        let data = Unsynced(UnsafeCell::new(0));
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                unsafe { *data.get() = 42 };
+                c.send(());
+            });
+            while !c.is_ready_with(Acquire) {
+                std::hint::spin_loop();
+            }
+            assert_eq!(unsafe { *data.get() }, 42);
+        });
+        assert!(c.is_ready_acquire());
+        c.receive();
+        assert!(!c.is_ready_with(SeqCst));
+    }
+
+    #[test]
+    fn is_ready_with_rejects_store_orderings() {
+        let c = Channel::<()>::new();
+        for ordering in [Release, AcqRel] {
+            assert!(panic::catch_unwind(AssertUnwindSafe(|| c.is_ready_with(ordering))).is_err());
+        }
+    }
+
+    #[test]
+    fn panicking_before_send_leaves_the_channel_usable() {
+        fn make_message() -> String {
+            panic!("Couldn't make the message");
+        }
+        let c = Channel::new();
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.send(make_message()))).is_err());
+        assert!(!c.is_ready());
+        c.send(String::from("made"));
+        assert_eq!(c.receive(), "made");
+    }
+
+    #[test]
+    fn committed_send_is_receivable() {
+        let c = Channel::new();
+        let handle = c.begin_send(1);
+        // Not receivable until committed
+        assert_eq!(c.try_receive(), Err(RecvError::Empty));
+        handle.commit();
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn aborted_send_gives_the_message_back() {
+        let c = Channel::new();
+        let handle = c.begin_send(String::from("first"));
+        assert_eq!(handle.abort(), "first");
+        assert_eq!(c.try_receive(), Err(RecvError::Empty));
+        // The channel can be sent on again
+        c.send(String::from("second"));
+        assert_eq!(c.receive(), "second");
+    }
+
+    #[test]
+    fn dropped_send_handle_aborts() {
+        let a = Arc::new(());
+        let c = Channel::new();
+        drop(c.begin_send(a.clone()));
+        assert_eq!(Arc::strong_count(&a), 1);
+        assert!(!c.is_ready());
+        c.begin_send(a.clone()).commit();
+        assert!(c.is_ready());
+    }
+
+    #[test]
+    fn cant_begin_a_second_send() {
+        let c = Channel::new();
+        let _handle = c.begin_send(1);
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.send(2))).is_err());
+    }
+
+    #[test]
+    fn relay_after_an_aborted_send() {
+        // Only a Relaxed flag between the abort and the relay, so the relay
+        // writing to's slot is only ordered after the abort reading it by the
+        // Acquire in relay_to, pairing with the Release in take_back. Under
+        // miri, a Relaxed claim there is reported as a data race.
+        let (from, to) = (Channel::new(), Channel::new());
+        let aborted = AtomicBool::new(false);
+        from.send(String::from("relayed"));
+        thread::scope(|s| {
+            s.spawn(|| {
+                drop(to.begin_send(String::from("aborted")));
+                aborted.store(true, Relaxed);
+            });
+            s.spawn(|| {
+                while !aborted.load(Relaxed) {
+                    std::hint::spin_loop();
+                }
+                assert!(crate::channels::transfer::relay(&from, &to));
+            });
+        });
+        assert_eq!(to.receive(), "relayed");
+    }
+
+    #[test]
+    fn receive_blocking_waits_for_a_later_send() {
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(std::time::Duration::from_millis(20));
+                c.send(7);
+            });
+            // Parked until send unparks us, not polling
+            assert_eq!(c.receive_blocking(), 7);
+        });
+        assert!(c.waiter.load(Relaxed).is_null());
+    }
+
+    #[test]
+    fn receive_blocking_after_send_returns_right_away() {
+        let c = Channel::new();
+        c.send("early");
+        assert_eq!(c.receive_blocking(), "early");
+    }
+
+    #[test]
+    fn on_ready_fires_on_send_with_the_message() {
+        let c = Channel::new();
+        let seen = Arc::new(Mutex::new(None));
+        let seen2 = seen.clone();
+        c.on_ready(move |m: &String| *seen2.lock().unwrap() = Some(m.clone()));
+        assert_eq!(*seen.lock().unwrap(), None);
+        thread::scope(|s| {
+            s.spawn(|| c.send(String::from("sent")));
+        });
+        assert_eq!(seen.lock().unwrap().as_deref(), Some("sent"));
+        assert_eq!(c.receive(), "sent");
+    }
+
+    #[test]
+    fn on_ready_after_send_is_called_right_away() {
+        let c = Channel::new();
+        c.send(3);
+        let seen = Arc::new(AtomicUsize::new(0));
+        let seen2 = seen.clone();
+        c.on_ready(move |m| seen2.store(*m, Relaxed));
+        assert_eq!(seen.load(Relaxed), 3);
+        // Peeking leaves the message where it was
+        assert!(c.is_ready());
+        assert_eq!(c.receive(), 3);
+        c.on_ready(|_| panic!("Nothing left to call this with!"));
+    }
+
+    #[test]
+    fn receive_waits_for_on_ready_to_finish() {
+        let c = Channel::new();
+        c.send(1);
+        let looking = Arc::new(AtomicBool::new(false));
+        thread::scope(|s| {
+            let looking2 = looking.clone();
+            s.spawn(|| {
+                c.on_ready(move |_| {
+                    looking2.store(true, Relaxed);
+                    thread::sleep(std::time::Duration::from_millis(20));
+                })
+            });
+            while !looking.load(Relaxed) {
+                thread::yield_now();
+            }
+            // Used to find ready reset by on_ready here, and panic
+            assert_eq!(c.receive(), 1);
+        });
+    }
+
+    #[test]
+    fn on_ready_twice_panics() {
+        let c = Channel::<i32>::new();
+        c.on_ready(|_| {});
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.on_ready(|_| {}))).is_err());
+        c.send(1);
+        assert_eq!(c.receive(), 1);
+    }
+
+    #[test]
+    fn callback_using_the_channel_doesnt_deadlock() {
+        let c = Arc::new(Channel::new());
+        let c2 = c.clone();
+        let second = Arc::new(AtomicBool::new(false));
+        let second2 = second.clone();
+        c.on_ready(move |_| {
+            // Not ready yet, and a second registration
+            assert_eq!(c2.try_receive(), Err(RecvError::Empty));
+            let again = panic::catch_unwind(AssertUnwindSafe(|| c2.on_ready(|_| {})));
+            second2.store(again.is_err(), Relaxed);
+        });
+        c.send(5);
+        assert!(second.load(Relaxed));
+        assert_eq!(c.receive(), 5);
+    }
+
+    #[test]
+    fn panicking_callback_still_publishes() {
+        let c = Channel::new();
+        c.on_ready(|_| panic!("callback"));
+        assert!(panic::catch_unwind(AssertUnwindSafe(|| c.send(2))).is_err());
+        assert_eq!(c.receive(), 2);
+    }
+
+    #[test]
+    fn peek_leaves_the_message_for_receive() {
+        let c = Channel::new();
+        assert_eq!(c.peek(), None);
+        c.send(42);
+        assert_eq!(c.peek(), Some(42));
+        assert_eq!(c.peek(), Some(42));
+        assert!(c.is_ready());
+        assert_eq!(c.receive(), 42);
+        assert_eq!(c.peek(), None);
+    }
+
+    #[test]
+    fn event_wakes_every_waiter_without_taking_the_message() {
+        let c = Channel::new();
+        let event = Event::new();
+        c.set_event_on_ready(&event);
+        let woken = AtomicUsize::new(0);
+        thread::scope(|s| {
+            for _ in 0..2 {
+                s.spawn(|| {
+                    event.wait();
+                    woken.fetch_add(1, Relaxed);
+                });
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+            assert_eq!(woken.load(Relaxed), 0);
+            c.send(String::from("for one of them"));
+        });
+        assert_eq!(woken.load(Relaxed), 2);
+        assert_eq!(c.receive(), "for one of them");
+    }
+
+    #[test]
+    fn event_is_set_right_away_after_send() {
+        let c = Channel::new();
+        c.send(1);
+        let event = Event::new();
+        c.set_event_on_ready(&event);
+        assert!(event.is_set());
+        c.receive();
+        let late = Event::new();
+        c.set_event_on_ready(&late);
+        assert!(!late.is_set());
+    }
+
+    #[test]
+    fn observe_after_sees_what_came_before_send() {
+        // Checked under miri as well, with many seeds. With a Relaxed load of
+        // ready in observe_after, miri finds prior still 0.
+        let c = Channel::new();
+        let prior = AtomicUsize::new(0);
+        assert!(!c.observe_after(&prior));
+        thread::scope(|s| {
+            s.spawn(|| {
+                prior.store(1, Release);
+                c.send(());
+            });
+            while !c.is_ready() {
+                thread::yield_now();
+            }
+            assert!(c.observe_after(&prior));
+        });
+        c.receive();
+        assert!(!c.observe_after(&prior));
+    }
+
+    #[test]
+    fn zero_sized_messages_round_trip() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Relaxed);
+            }
+        }
+        let c = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| c.send(()));
+        });
+        c.receive();
+        let c = Channel::new();
+        c.send(Token);
+        let token = c.receive();
+        assert_eq!(DROPS.load(Relaxed), 0);
+        drop(token);
+        assert_eq!(DROPS.load(Relaxed), 1);
+        // One never received is still dropped with the channel
+        let c = Channel::new();
+        c.send(Token);
+        drop(c);
+        assert_eq!(DROPS.load(Relaxed), 2);
+    }
+
+    #[test]
+    fn send_and_receive_dont_take_the_on_ready_lock() {
+        let c = Channel::new();
+        // Would deadlock if either of them locked
+        let _held = c.lock_on_ready();
+        c.send(1);
+        assert_eq!(c.try_receive(), Ok(1));
+    }
+
+    #[test]
+    fn on_ready_racing_a_send_is_called_once() {
+        let rounds = if cfg!(miri) { 20 } else { 1000 };
+        for _ in 0..rounds {
+            let c = Channel::new();
+            let called = Arc::new(AtomicUsize::new(0));
+            let called2 = called.clone();
+            thread::scope(|s| {
+                s.spawn(|| c.send(7));
+                // Used to panic when it found the sender publishing without
+                // a callback
+                s.spawn(|| {
+                    c.on_ready(move |m| {
+                        assert_eq!(*m, 7);
+                        called2.fetch_add(1, Relaxed);
+                    })
+                });
+            });
+            // Either by the sender, or right away, as the message is still there
+            assert_eq!(called.load(Relaxed), 1);
+            assert_eq!(c.receive(), 7);
+        }
+    }
+}
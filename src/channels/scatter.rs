@@ -0,0 +1,78 @@
+use std::thread;
+use crate::channels::compile_time_oneshot;
+use crate::locks::semaphore::Semaphore;
+
+/// Runs worker on every input, each on its own thread, with at most
+/// max_in_flight of them running at the same time. Every worker sends its
+/// result back through its own oneshot, and the results are returned in the
+/// same order as the inputs.
+///
+/// Panics if max_in_flight is zero, as nothing could ever run.
+pub fn bounded_scatter<In, Out, F>(inputs: impl IntoIterator<Item = In>, max_in_flight: usize, worker: F) -> Vec<Out>
+where
+    In: Send,
+    Out: Send,
+    F: Fn(In) -> Out + Sync,
+{
+    assert!(max_in_flight > 0, "max_in_flight must be at least 1!");
+    let semaphore = Semaphore::new(max_in_flight);
+    let worker = &worker;
+    let receivers: Vec<_> = thread::scope(|s| {
+        inputs
+            .into_iter()
+            .map(|input| {
+                // Blocks here until one of the running workers is done, before
+                // spawning the next one
+                let permit = semaphore.acquire();
+                let (sender, receiver) = compile_time_oneshot::channel();
+                s.spawn(move || {
+                    sender.send(worker(input));
+                    drop(permit);
+                });
+                receiver
+            })
+            .collect()
+    });
+    // The scope has joined every worker, so every result is ready (a worker
+    // that panicked would have made the scope panic)
+    receivers.into_iter().map(|r| r.receive().unwrap()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering::SeqCst;
+    use std::time::Duration;
+
+    #[test]
+    fn at_most_max_in_flight_at_once() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let outputs = bounded_scatter(0..20, 4, |i| {
+            let now = running.fetch_add(1, SeqCst) + 1;
+            peak.fetch_max(now, SeqCst);
+            thread::sleep(Duration::from_millis(5));
+            running.fetch_sub(1, SeqCst);
+            i * 2
+        });
+        assert_eq!(outputs, (0..20).map(|i| i * 2).collect::<Vec<_>>());
+        assert!(peak.load(SeqCst) <= 4, "{} at once", peak.load(SeqCst));
+    }
+
+    #[test]
+    fn results_in_input_order() {
+        // Later inputs finish first
+        let outputs = bounded_scatter(0..8u64, 8, |i| {
+            thread::sleep(Duration::from_millis(2 * (8 - i)));
+            i
+        });
+        assert_eq!(outputs, (0..8).collect::<Vec<_>>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_in_flight_panics() {
+        bounded_scatter(0..1, 0, |i| i);
+    }
+}
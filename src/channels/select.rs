@@ -0,0 +1,106 @@
+use crate::channels::safer_oneshot;
+use crate::channels::wake;
+
+// Which of the channels a message was received from
+#[derive(Debug, PartialEq, Eq)]
+pub enum Selected<A, B> {
+    A(A),
+    B(B),
+}
+
+/// Receives from whichever of the two channels has a message, checking a
+/// first, so a wins if both are ready. Returns None without waiting if
+/// neither is ready (or closed).
+///
+/// Only one message is ever consumed, the other channel is left untouched.
+pub fn biased_try2<A, B>(a: &safer_oneshot::Channel<A>, b: &safer_oneshot::Channel<B>) -> Option<Selected<A, B>> {
+    if let Ok(message) = a.try_receive() {
+        return Some(Selected::A(message));
+    }
+    b.try_receive().ok().map(Selected::B)
+}
+
+/// Parks until either channel has a message, then receives it. If both are
+/// ready by the time we look, a wins, like in biased_try2.
+///
+/// Registers this thread as the waiter on both channels, so both senders
+/// unpark it, which means no other thread may be waiting on either of them
+/// (see safer_oneshot's receive_blocking). The channel that loses keeps its
+/// message, and can still be received from later.
+///
+/// Panics if both channels are closed without a message
+pub fn select2<A, B>(a: &safer_oneshot::Channel<A>, b: &safer_oneshot::Channel<B>) -> Selected<A, B> {
+    a.register_waiter();
+    b.register_waiter();
+    loop {
+        if let Some(selected) = biased_try2(a, b) {
+            return selected;
+        }
+        if a.is_closed() && b.is_closed() {
+            panic!("Both channels were closed!");
+        }
+        // Unparked by either send (or close), or spuriously, either way
+        // we check both again
+        wake::park();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn biased_try2_takes_only_the_ready_one() {
+        let (a, b) = (safer_oneshot::Channel::<i32>::new(), safer_oneshot::Channel::new());
+        assert_eq!(biased_try2(&a, &b), None);
+        b.send("b");
+        assert_eq!(biased_try2(&a, &b), Some(Selected::B("b")));
+        // a is still pending, and can still be sent to
+        assert!(!a.is_ready());
+        a.send(1);
+        assert_eq!(biased_try2(&a, &b), Some(Selected::A(1)));
+    }
+
+    #[test]
+    fn biased_try2_prefers_a() {
+        let (a, b) = (safer_oneshot::Channel::new(), safer_oneshot::Channel::new());
+        a.send(1);
+        b.send(2);
+        assert_eq!(biased_try2(&a, &b), Some(Selected::A(1)));
+        assert!(b.is_ready());
+        assert_eq!(biased_try2(&a, &b), Some(Selected::B(2)));
+    }
+
+    #[test]
+    fn select2_takes_the_earlier_send() {
+        let (a, b) = (safer_oneshot::Channel::new(), safer_oneshot::Channel::new());
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(50));
+                a.send("late");
+            });
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(5));
+                b.send("early");
+            });
+            assert_eq!(select2(&a, &b), Selected::B("early"));
+        });
+        // The loser keeps its message
+        assert_eq!(a.receive(), "late");
+    }
+
+    #[test]
+    fn select2_waits_past_a_closed_channel() {
+        let (a, b) = (safer_oneshot::Channel::<i32>::new(), safer_oneshot::Channel::new());
+        a.close();
+        thread::scope(|s| {
+            s.spawn(|| {
+                thread::sleep(Duration::from_millis(10));
+                b.send(2);
+            });
+            assert_eq!(select2(&a, &b), Selected::B(2));
+        });
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::VecDeque;
+use std::sync::{Condvar, Mutex};
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+
+// Like mutex_based::Channel, but keeps track of roughly how many bytes are
+// queued, using size to estimate each message's size, for channels carrying
+// payloads of very different sizes where the number of messages doesn't say
+// much about memory use. A sender can check queued_bytes to decide when to
+// back off.
+pub struct SizedChannel<T, F> {
+    // Each message is kept with its estimated size, so the total goes down by
+    // exactly what it went up by, even if size wouldn't give the same answer
+    // twice
+    queue: Mutex<VecDeque<(T, usize)>>,
+    item_ready: Condvar,
+    size: F,
+    // Only changed while holding the queue lock, so Relaxed is enough, the
+    // atomic is just so queued_bytes doesn't need the lock
+    queued_bytes: AtomicUsize,
+}
+
+impl<T, F: Fn(&T) -> usize> SizedChannel<T, F> {
+    pub fn new(size: F) -> Self {
+        Self {
+            queue: Mutex::new(VecDeque::new()),
+            item_ready: Condvar::new(),
+            size,
+            queued_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn send(&self, message: T) {
+        // Estimated before locking, size might not be cheap
+        let bytes = (self.size)(&message);
+        let mut b = self.queue.lock().unwrap();
+        b.push_back((message, bytes));
+        self.queued_bytes.fetch_add(bytes, Relaxed);
+        drop(b);
+        self.item_ready.notify_one();
+    }
+
+    // Blocks until a message is available
+    pub fn receive(&self) -> T {
+        let mut b = self.queue.lock().unwrap();
+        loop {
+            if let Some((message, bytes)) = b.pop_front() {
+                self.queued_bytes.fetch_sub(bytes, Relaxed);
+                return message;
+            }
+            b = self.item_ready.wait(b).unwrap();
+        }
+    }
+
+    // The estimated size of everything queued right now, may be stale by the
+    // time the caller looks at it
+    pub fn queued_bytes(&self) -> usize {
+        self.queued_bytes.load(Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queued_bytes_follows_sends_and_receives() {
+        let c = SizedChannel::new(|s: &String| s.len());
+        assert_eq!(c.queued_bytes(), 0);
+        for s in ["a", "bcd", "efghij"] {
+            c.send(String::from(s));
+        }
+        assert_eq!(c.queued_bytes(), 10);
+        assert_eq!(c.receive(), "a");
+        assert_eq!(c.queued_bytes(), 9);
+        assert_eq!(c.receive(), "bcd");
+        assert_eq!(c.queued_bytes(), 6);
+        assert_eq!(c.receive(), "efghij");
+        assert_eq!(c.queued_bytes(), 0);
+    }
+
+    #[test]
+    fn takes_off_the_size_it_added() {
+        // A size function that never gives the same answer twice
+        let calls = AtomicUsize::new(1);
+        let c = SizedChannel::new(|_: &()| calls.fetch_add(1, Relaxed));
+        c.send(());
+        c.send(());
+        assert_eq!(c.queued_bytes(), 3);
+        c.receive();
+        assert_eq!(c.queued_bytes(), 2);
+    }
+}
@@ -0,0 +1,181 @@
+use std::cell::{Cell, UnsafeCell};
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::{Acquire, Release};
+use crate::channels::ring_buffer::CachePadded;
+
+// The same single producer, single consumer ring as ring_buffer, but with the
+// capacity picked at runtime, and owned Sender and Receiver halves sharing the
+// ring through an Arc, like std's channels, instead of borrowing it. So they
+// can be moved into any thread, not just scoped ones, at the cost of one
+// allocation for the ring.
+//
+// head, tail, and the cached copies of the other side's position work just
+// like in ring_buffer, see there. Full and empty are told apart the same way
+// too: both positions keep counting up, so every slot can be used, full is
+// tail being capacity ahead of head.
+//
+// The halves aren't Clone, and the Cells holding their positions make them
+// !Sync, so there's only ever one thread sending and one receiving, even
+// though both take &self.
+pub fn channel<T>(capacity: usize) -> (Sender<T>, Receiver<T>) {
+    assert!(capacity > 0, "Capacity must be at least 1!");
+    let shared = Arc::new(Shared {
+        slots: (0..capacity).map(|_| UnsafeCell::new(MaybeUninit::uninit())).collect(),
+        head: CachePadded(AtomicUsize::new(0)),
+        tail: CachePadded(AtomicUsize::new(0)),
+    });
+    (
+        Sender { shared: shared.clone(), tail: Cell::new(0), cached_head: Cell::new(0) },
+        Receiver { shared, head: Cell::new(0), cached_tail: Cell::new(0) },
+    )
+}
+
+struct Shared<T> {
+    slots: Box<[UnsafeCell<MaybeUninit<T>>]>,
+    head: CachePadded<AtomicUsize>,
+    tail: CachePadded<AtomicUsize>,
+}
+
+// Messages only ever go from the Sender's thread to the Receiver's
+unsafe impl<T> Sync for Shared<T> where T: Send {}
+
+pub struct Sender<T> {
+    shared: Arc<Shared<T>>,
+    tail: Cell<usize>,
+    cached_head: Cell<usize>,
+}
+
+pub struct Receiver<T> {
+    shared: Arc<Shared<T>>,
+    head: Cell<usize>,
+    cached_tail: Cell<usize>,
+}
+
+// Dropped once both halves are gone. Anything between head and tail was sent
+// but never received.
+impl<T> Drop for Shared<T> {
+    fn drop(&mut self) {
+        let tail = *self.tail.0.get_mut();
+        let mut head = *self.head.0.get_mut();
+        let capacity = self.slots.len();
+        while head != tail {
+            unsafe { self.slots[head % capacity].get_mut().assume_init_drop() }
+            head = head.wrapping_add(1);
+        }
+    }
+}
+
+impl<T> Sender<T> {
+    /// Gives the message back if the ring is full
+    pub fn try_send(&self, message: T) -> Result<(), T> {
+        let capacity = self.shared.slots.len();
+        let tail = self.tail.get();
+        if tail.wrapping_sub(self.cached_head.get()) == capacity {
+            // Acquire, so the receiver is done reading the slot we're about
+            // to write
+            self.cached_head.set(self.shared.head.0.load(Acquire));
+            if tail.wrapping_sub(self.cached_head.get()) == capacity {
+                return Err(message);
+            }
+        }
+        // Safety: The slot is between head and tail + capacity, so it's empty,
+        // and only we write to slots
+        unsafe { (*self.shared.slots[tail % capacity].get()).write(message); }
+        self.tail.set(tail.wrapping_add(1));
+        // Release the message to the receiver
+        self.shared.tail.0.store(tail.wrapping_add(1), Release);
+        Ok(())
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shared.slots.len()
+    }
+}
+
+impl<T> Receiver<T> {
+    /// Returns None if the ring is empty
+    pub fn try_recv(&self) -> Option<T> {
+        let head = self.head.get();
+        if head == self.cached_tail.get() {
+            // Acquire, pairs with the Release in try_send
+            self.cached_tail.set(self.shared.tail.0.load(Acquire));
+            if head == self.cached_tail.get() {
+                return None;
+            }
+        }
+        // Safety: The slot is between head and tail, so it holds a message,
+        // and only we read from slots
+        let capacity = self.shared.slots.len();
+        let message = unsafe { (*self.shared.slots[head % capacity].get()).assume_init_read() };
+        self.head.set(head.wrapping_add(1));
+        // Release, so we're done reading before the sender reuses the slot
+        self.shared.head.0.store(head.wrapping_add(1), Release);
+        Some(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn full_ring_gives_the_message_back() {
+        let (s, r) = channel(2);
+        assert_eq!(s.capacity(), 2);
+        s.try_send(1).unwrap();
+        s.try_send(2).unwrap();
+        assert_eq!(s.try_send(3), Err(3));
+        assert_eq!(r.try_recv(), Some(1));
+        s.try_send(3).unwrap();
+        assert_eq!((r.try_recv(), r.try_recv(), r.try_recv()), (Some(2), Some(3), None));
+    }
+
+    #[test]
+    fn unreceived_messages_are_dropped_with_the_ring() {
+        let a = Arc::new(());
+        let (s, r) = channel(3);
+        for _ in 0..3 {
+            s.try_send(a.clone()).unwrap();
+        }
+        drop(r.try_recv());
+        drop((s, r));
+        assert_eq!(Arc::strong_count(&a), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 1!")]
+    fn zero_capacity_panics() {
+        channel::<i32>(0);
+    }
+
+    #[test]
+    fn messages_arrive_in_order() {
+        const MESSAGES: usize = if cfg!(miri) { 500 } else { 200_000 };
+        // Small, so both sides keep running into the other, and yielding
+        // rather than spinning, so this doesn't crawl on a single core
+        let (s, r) = channel(4);
+        let producer = thread::spawn(move || {
+            for i in 0..MESSAGES {
+                let mut m = i;
+                while let Err(back) = s.try_send(m) {
+                    m = back;
+                    thread::yield_now();
+                }
+            }
+        });
+        for i in 0..MESSAGES {
+            let m = loop {
+                if let Some(m) = r.try_recv() {
+                    break m;
+                }
+                thread::yield_now();
+            };
+            assert_eq!(m, i);
+        }
+        producer.join().unwrap();
+        assert_eq!(r.try_recv(), None);
+    }
+}
@@ -0,0 +1,139 @@
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::AtomicU8;
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+
+// Holds one value that any number of threads can try to take, but only one
+// of them gets it, the rest get None. Like a single piece of work up for
+// grabs between workers.
+//
+// Everything goes through one state word, and every step is a
+// compare_exchange on it, so only one thread can make each step: one publish
+// gets to write, and one try_steal gets to read.
+pub struct StealBox<T> {
+    value: UnsafeCell<MaybeUninit<T>>,
+    state: AtomicU8,
+}
+
+const EMPTY: u8 = 0;
+// publish is writing the value
+const WRITING: u8 = 1;
+const READY: u8 = 2;
+// Someone stole the value, this never changes again
+const TAKEN: u8 = 3;
+
+unsafe impl<T> Sync for StealBox<T> where T: Send {}
+
+impl<T> Drop for StealBox<T> {
+    fn drop(&mut self) {
+        if *self.state.get_mut() == READY {
+            unsafe { self.value.get_mut().assume_init_drop() }
+        }
+    }
+}
+
+impl<T> Default for StealBox<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> StealBox<T> {
+    pub const fn new() -> Self {
+        Self { value: UnsafeCell::new(MaybeUninit::uninit()), state: AtomicU8::new(EMPTY) }
+    }
+
+    // Panics if something was already published, a StealBox only ever holds
+    // one value
+    pub fn publish(&self, value: T) {
+        if self.state.compare_exchange(EMPTY, WRITING, Relaxed, Relaxed).is_err() {
+            panic!("Can't publish more than one value!");
+        }
+        // Safety: Only the one publish that got from EMPTY to WRITING gets here,
+        // and no stealer reads before READY
+        unsafe { (*self.value.get()).write(value); }
+        // Release the value to whoever steals it
+        self.state.store(READY, Release);
+    }
+
+    // None if there's nothing published yet, or someone else got it first
+    pub fn try_steal(&self) -> Option<T> {
+        // Acquire pairs with the Release in publish. Whoever gets from READY
+        // to TAKEN owns the value, every other stealer fails here, so it's
+        // read exactly once.
+        self.state.compare_exchange(READY, TAKEN, Acquire, Relaxed).ok()?;
+        // Safety: READY means the value was written, and we're the only one
+        // that got to take it
+        Some(unsafe { (*self.value.get()).assume_init_read() })
+    }
+
+    // Whether there's a value up for grabs right now. Can be out of date
+    // right away, try_steal is the only way to know.
+    pub fn is_ready(&self) -> bool {
+        self.state.load(Relaxed) == READY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::thread;
+
+    #[test]
+    fn exactly_one_stealer_gets_the_value() {
+        for round in 0..100 {
+            let b = StealBox::new();
+            let stolen = AtomicUsize::new(0);
+            thread::scope(|s| {
+                for _ in 0..8 {
+                    s.spawn(|| {
+                        // Keep trying until it's either ours or gone
+                        loop {
+                            if let Some(value) = b.try_steal() {
+                                assert_eq!(value, format!("job {round}"));
+                                stolen.fetch_add(1, Relaxed);
+                                return;
+                            }
+                            if b.state.load(Relaxed) == TAKEN {
+                                return;
+                            }
+                            thread::yield_now();
+                        }
+                    });
+                }
+                s.spawn(|| b.publish(format!("job {round}")));
+            });
+            assert_eq!(stolen.into_inner(), 1);
+        }
+    }
+
+    #[test]
+    fn nothing_to_steal_before_publish() {
+        let b = StealBox::new();
+        assert!(!b.is_ready());
+        assert_eq!(b.try_steal(), None);
+        b.publish(1);
+        assert!(b.is_ready());
+        assert_eq!(b.try_steal(), Some(1));
+        assert!(!b.is_ready());
+        assert_eq!(b.try_steal(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't publish more than one value!")]
+    fn publishing_twice_panics() {
+        let b = StealBox::new();
+        b.publish(1);
+        b.publish(2);
+    }
+
+    #[test]
+    fn drops_a_value_nobody_stole() {
+        let value = std::sync::Arc::new(());
+        let b = StealBox::new();
+        b.publish(value.clone());
+        drop(b);
+        assert_eq!(std::sync::Arc::strong_count(&value), 1);
+    }
+}
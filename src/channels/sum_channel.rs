@@ -0,0 +1,105 @@
+use std::ops::AddAssign;
+use std::sync::atomic::Ordering::Relaxed;
+use std::sync::atomic::{AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize};
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, AtomicUsize};
+
+// Like CounterChannel, but every send carries a number, and the receiver gets
+// the sum of everything sent since it last looked, for high frequency metrics
+// where only the total matters. Sending is a single fetch_add, nothing is
+// queued.
+//
+// Relaxed is enough everywhere, as the sum is the only data.
+//
+// Overflow: The sum wraps around on overflow, like fetch_add does.
+pub struct SumChannel<T: Summable> {
+    sum: T::Atomic,
+}
+
+// The integer types that have an atomic version to add into
+pub trait Summable: AddAssign + Copy {
+    type Atomic;
+    fn zero() -> Self::Atomic;
+    fn fetch_add(atomic: &Self::Atomic, value: Self);
+    fn take(atomic: &Self::Atomic) -> Self;
+}
+
+macro_rules! impl_summable {
+    ($($t:ty => $atomic:ty),*) => {
+        $(impl Summable for $t {
+            type Atomic = $atomic;
+            fn zero() -> $atomic {
+                <$atomic>::new(0)
+            }
+            fn fetch_add(atomic: &$atomic, value: $t) {
+                atomic.fetch_add(value, Relaxed);
+            }
+            fn take(atomic: &$atomic) -> $t {
+                atomic.swap(0, Relaxed)
+            }
+        })*
+    };
+}
+
+impl_summable!(
+    u8 => AtomicU8, u16 => AtomicU16, u32 => AtomicU32, u64 => AtomicU64, usize => AtomicUsize,
+    i8 => AtomicI8, i16 => AtomicI16, i32 => AtomicI32, i64 => AtomicI64, isize => AtomicIsize
+);
+
+impl<T: Summable> Default for SumChannel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Summable> SumChannel<T> {
+    pub fn new() -> Self {
+        Self { sum: T::zero() }
+    }
+
+    pub fn send(&self, value: T) {
+        T::fetch_add(&self.sum, value);
+    }
+
+    // Returns the sum of everything sent since the last receive, and starts
+    // from zero again. swap makes sure no send is lost or counted twice in
+    // between reading and resetting.
+    pub fn receive(&self) -> T {
+        T::take(&self.sum)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn sums_sends_from_many_threads() {
+        let c = SumChannel::<u64>::new();
+        thread::scope(|s| {
+            for t in 0..4 {
+                let c = &c;
+                s.spawn(move || {
+                    for i in 1..=1000 {
+                        c.send(i * (t + 1));
+                    }
+                });
+            }
+        });
+        // 500500 for each of 1 + 2 + 3 + 4
+        assert_eq!(c.receive(), 500500 * 10);
+        assert_eq!(c.receive(), 0);
+    }
+
+    #[test]
+    fn wraps_on_overflow() {
+        let c = SumChannel::<u8>::new();
+        c.send(200);
+        c.send(100);
+        assert_eq!(c.receive(), 44);
+        let c = SumChannel::<i32>::new();
+        c.send(-5);
+        c.send(3);
+        assert_eq!(c.receive(), -2);
+    }
+}
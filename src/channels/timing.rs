@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use crate::channels::filter::Receive;
+
+// A receiver that remembers how long it was between each message and the
+// one before, for the last capacity messages, to see how regular (or
+// jittery) a stream of messages is.
+// The time is taken when receive returns, so it includes however long the
+// caller took before calling receive again, not just how long the messages
+// took to arrive.
+pub struct JitterReceiver<R> {
+    receiver: R,
+    capacity: usize,
+    timing: Mutex<Timing>,
+}
+
+struct Timing {
+    last: Option<Instant>,
+    // Oldest first, never more than capacity
+    intervals: VecDeque<Duration>,
+}
+
+impl<R> JitterReceiver<R> {
+    // Panics if capacity is zero, there has to be room for an interval
+    pub fn new(receiver: R, capacity: usize) -> Self {
+        assert!(capacity > 0, "Capacity must be at least 1!");
+        Self {
+            receiver,
+            capacity,
+            timing: Mutex::new(Timing { last: None, intervals: VecDeque::with_capacity(capacity) }),
+        }
+    }
+
+    pub fn receive<T>(&self) -> T
+    where
+        R: Receive<T>,
+    {
+        let message = self.receiver.receive();
+        let now = Instant::now();
+        let mut timing = self.timing.lock().unwrap();
+        // The first message has nothing to measure from
+        if let Some(last) = timing.last.replace(now) {
+            if timing.intervals.len() == self.capacity {
+                timing.intervals.pop_front();
+            }
+            timing.intervals.push_back(now - last);
+        }
+        message
+    }
+
+    // The intervals between the last messages, oldest first
+    pub fn recent_intervals(&self) -> Vec<Duration> {
+        self.timing.lock().unwrap().intervals.iter().copied().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::mutex_based::Channel;
+    use std::thread;
+
+    #[test]
+    fn records_the_spacing_of_the_messages() {
+        const SPACING: Duration = Duration::from_millis(20);
+        let r = JitterReceiver::new(Channel::new(), 4);
+        // The sender only starts sleeping once the message before was
+        // received, so no gap can be shorter than SPACING, however late the
+        // receiver wakes up for any of them
+        let received = Channel::new();
+        thread::scope(|s| {
+            s.spawn(|| {
+                for i in 0..6 {
+                    thread::sleep(SPACING);
+                    r.receiver.send(i);
+                    received.receive();
+                }
+            });
+            for i in 0..6 {
+                assert_eq!(r.receive(), i);
+                received.send(());
+            }
+        });
+        let intervals = r.recent_intervals();
+        // Only the last 4 of the 5
+        assert_eq!(intervals.len(), 4);
+        for interval in &intervals {
+            assert!(*interval >= SPACING, "{interval:?} is too short");
+        }
+        // Loose, a loaded machine can make any one of them run long
+        let total: Duration = intervals.iter().sum();
+        assert!(total < SPACING * 4 * 25, "{total:?} is too long");
+    }
+
+    #[test]
+    fn the_first_message_has_no_interval() {
+        let r = JitterReceiver::new(Channel::new(), 2);
+        r.receiver.send(1);
+        r.receive::<i32>();
+        assert!(r.recent_intervals().is_empty());
+        r.receiver.send(2);
+        r.receive::<i32>();
+        assert_eq!(r.recent_intervals().len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Capacity must be at least 1!")]
+    fn zero_capacity_panics() {
+        JitterReceiver::new(Channel::<()>::new(), 0);
+    }
+}
@@ -0,0 +1,56 @@
+use crate::channels::safer_oneshot;
+
+/// If `from` has a message ready, moves it into `to` and returns true,
+/// otherwise leaves both channels untouched and returns false.
+///
+/// The message is moved directly from one channel's slot into the other's,
+/// without passing through an owned variable. After a relay `from` behaves
+/// like a channel whose message was received, and `to` like one that was sent
+/// to, so it can be received from as usual.
+///
+/// Panics if `to` has already been sent to, in which case the message stays
+/// in `from`.
+pub fn relay<T>(from: &safer_oneshot::Channel<T>, to: &safer_oneshot::Channel<T>) -> bool {
+    from.relay_to(to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+    use crate::channels::safer_oneshot::Channel;
+
+    #[test]
+    fn relays_a_ready_message() {
+        let from = Channel::new();
+        let to = Channel::new();
+        from.send(String::from("hi"));
+        assert!(relay(&from, &to));
+        assert!(!from.is_ready());
+        assert!(to.is_ready());
+        assert_eq!(to.receive(), "hi");
+    }
+
+    #[test]
+    fn nothing_to_relay() {
+        let from = Channel::<String>::new();
+        let to = Channel::new();
+        assert!(!relay(&from, &to));
+        assert!(!to.is_ready());
+        // to can still be sent to as usual
+        to.send(String::from("direct"));
+        assert_eq!(to.receive(), "direct");
+    }
+
+    #[test]
+    fn message_stays_in_from_if_to_was_sent_to() {
+        let from = Channel::new();
+        let to = Channel::new();
+        from.send(String::from("x"));
+        to.send(String::from("y"));
+        let result = panic::catch_unwind(AssertUnwindSafe(|| relay(&from, &to)));
+        assert!(result.is_err());
+        assert_eq!(from.receive(), "x");
+        assert_eq!(to.receive(), "y");
+    }
+}
@@ -1,7 +1,11 @@
-use std::cell::UnsafeCell;
-use std::mem::MaybeUninit;
-use std::sync::atomic::AtomicBool;
-use std::sync::atomic::Ordering::{Acquire, Release};
+// Only uses core, so this module can be copied as is into a no_std crate
+// (without the Tracker, which is an empty struct outside of tests anyway).
+// safer_oneshot can't, it needs std for its Mutex and for parking threads.
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use crate::channels::leak_check::Tracker;
 
 // Typical use case: sending only one message from one thread to another
 // This is a minimal implementation without putting much thought into the
@@ -11,18 +15,43 @@ use std::sync::atomic::Ordering::{Acquire, Release};
 // 1. Calling send more than once, could cause a data race
 // 2. Calling receive more than once results in two copies of the message,
 // even if T does not implement Copy and cannot be safely copied.
-// 3. Lack of Drop implementation, MaybeUninit doesn't track if it's been
-// initialized or dropped, if a message is sent, but never received, it will
-// never be dropped.
+// 3. (Fixed) MaybeUninit doesn't track if it's been initialized or dropped,
+// so without a Drop implementation a message that was sent, but never
+// received, would never be dropped. Drop now checks ready, which receive
+// resets, like the safer variant does.
+//
+// Zero-sized messages like () need nothing special here, or in the other
+// oneshots: a MaybeUninit of a ZST has no bytes to be uninitialized, so write
+// and assume_init_read don't touch memory at all. There's still a value
+// though, so ready has to be tracked and drop still has to run for it (a ZST
+// can have a Drop impl), exactly like for any other T.
 pub struct Channel<T> {
     message: UnsafeCell<MaybeUninit<T>>,
     ready: AtomicBool,
+    tracker: Tracker,
 }
 
 // Tell compiler our channel is safe to share between threads, as long as
 // T is Send
 unsafe impl<T> Sync for Channel<T> where T: Send {}
 
+// Same as safer_oneshot, dropping takes exclusive access, so get_mut works
+// without any atomic operations
+impl<T> Drop for Channel<T> {
+    fn drop(&mut self) {
+        if *self.ready.get_mut() {
+            unsafe { self.message.get_mut().assume_init_drop() }
+            self.tracker.message_gone();
+        }
+    }
+}
+
+impl<T> Default for Channel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<T> Channel<T> {
     // A new channel is empty, with message being uninitialized and ready set
     // to false
@@ -30,10 +59,29 @@ impl<T> Channel<T> {
         Self {
             message: UnsafeCell::new(MaybeUninit::uninit()),
             ready: AtomicBool::new(false),
+            tracker: Tracker::new(),
         }
     }
 
-    // Safety: Only call this once!
+    // Empties the channel so it can be used for another message, dropping the
+    // message if one was sent but never received. For pooling channels,
+    // rather than making a new one for every message.
+    /// # Safety
+    /// Every send and receive since the channel was made (or last reset)
+    /// must have kept to their contracts, as ready is all we have to go on to
+    /// know whether there's a message to drop.
+    // Taking self mutably already makes sure no send or receive is running at
+    // the same time, and that whatever they did is visible to us.
+    pub unsafe fn reset(&mut self) {
+        if *self.ready.get_mut() {
+            self.message.get_mut().assume_init_drop();
+            self.tracker.message_gone();
+        }
+        *self.ready.get_mut() = false;
+    }
+
+    /// # Safety
+    /// Only call this once!
     // We're leaving the call only once up to the caller of this code.
     // Dereference the pointer to the MaybeUninit<T> and call MaybeUninit::write
     // The atomic store releases the message to the receiver, initialization will be
@@ -41,6 +89,7 @@ impl<T> Channel<T> {
     // acquire ordering
     pub unsafe fn send(&self, message: T) {
         (*self.message.get()).write(message);
+        self.tracker.message_sent();
         self.ready.store(true, Release);
     }
 
@@ -51,14 +100,96 @@ impl<T> Channel<T> {
         self.ready.load(Acquire)
     }
 
-    // Safety: Only call this once,
-    // and only after is_ready() returns true!
+    /// # Safety
+    /// Only call this once,
+    /// and only after is_ready() returns true!
     // Deref the pointer to the MaybeUninit<T> and
     // call MaybeUninit::assume_init_read on it
     // We unsafely assume that it's been initialized,
     // and that it isn't being used to produce multiple
     // copies of non-Copy objects.
+    // Resets ready afterwards, so Drop doesn't drop the message a second
+    // time. Relaxed is enough, only Drop (which has exclusive access) looks at
+    // it after this.
     pub unsafe fn receive(&self) -> T {
-        (*self.message.get()).assume_init_read()
+        let message = (*self.message.get()).assume_init_read();
+        self.tracker.message_gone();
+        self.ready.store(false, Relaxed);
+        message
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn unreceived_message_is_dropped_once() {
+        let message = Arc::new(());
+        let c = Channel::new();
+        unsafe { c.send(message.clone()) };
+        assert_eq!(Arc::strong_count(&message), 2);
+        drop(c);
+        assert_eq!(Arc::strong_count(&message), 1);
+    }
+
+    #[test]
+    fn received_message_isnt_dropped_again() {
+        let message = Arc::new(());
+        let c = Channel::new();
+        unsafe { c.send(message.clone()) };
+        let received = unsafe { c.receive() };
+        drop(c);
+        assert_eq!(Arc::strong_count(&message), 2);
+        drop(received);
+        assert_eq!(Arc::strong_count(&message), 1);
+        // Nothing sent, nothing to drop
+        drop(Channel::<Arc<()>>::new());
+    }
+
+    #[test]
+    fn reset_drops_the_pending_message_for_reuse() {
+        let message = Arc::new(());
+        let mut c = Channel::new();
+        unsafe { c.send(message.clone()) };
+        unsafe { c.reset() };
+        assert_eq!(Arc::strong_count(&message), 1);
+        assert!(!c.is_ready());
+        unsafe { c.send(Arc::new(())) };
+        let second = unsafe { c.receive() };
+        assert!(!Arc::ptr_eq(&second, &message));
+        // Nothing pending, nothing to drop
+        unsafe { c.reset() };
+        assert_eq!(Arc::strong_count(&second), 1);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn zero_sized_messages_round_trip() {
+        static DROPS: AtomicUsize = AtomicUsize::new(0);
+        struct Token;
+        impl Drop for Token {
+            fn drop(&mut self) {
+                DROPS.fetch_add(1, Relaxed);
+            }
+        }
+        let c = Channel::new();
+        unsafe {
+            c.send(());
+            c.receive();
+        }
+        let c = Channel::new();
+        let token = unsafe {
+            c.send(Token);
+            c.receive()
+        };
+        assert_eq!(DROPS.load(Relaxed), 0);
+        drop(token);
+        assert_eq!(DROPS.load(Relaxed), 1);
+        // One never received is still dropped with the channel
+        let c = Channel::new();
+        unsafe { c.send(Token) };
+        drop(c);
+        assert_eq!(DROPS.load(Relaxed), 2);
+    }
+}
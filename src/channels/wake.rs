@@ -0,0 +1,217 @@
+use std::hint;
+use std::thread::{self, Thread};
+#[cfg(test)]
+use std::{cell::RefCell, marker::PhantomData, sync::Arc};
+
+// The oneshot channels don't block on their own, the receiver has to wait
+// for is_ready to become true somehow. A WakeStrategy is how it waits in
+// between checks.
+// Which one is best depends on how long the wait is expected to be, and on
+// whether burning CPU time is acceptable for lower latency.
+pub trait WakeStrategy {
+    // Called repeatedly until the condition being waited on is true, so
+    // returning early (spuriously) is always fine.
+    fn wait(&self);
+}
+
+// Parks the waiting thread, costs no CPU time while waiting. Something has to
+// unpark it again: receive_with registers the waiting thread with the
+// channel, and send (or close, or the sender going away) unparks it, so
+// nobody else has to.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParkStrategy;
+
+// Busy spins, lowest latency for very short waits, but keeps a core busy
+// the whole time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpinStrategy;
+
+// Gives other threads a chance to run between checks, somewhere in between
+// spinning and parking.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct YieldStrategy;
+
+impl WakeStrategy for ParkStrategy {
+    fn wait(&self) {
+        park();
+    }
+}
+
+impl WakeStrategy for SpinStrategy {
+    fn wait(&self) {
+        hint::spin_loop();
+    }
+}
+
+impl WakeStrategy for YieldStrategy {
+    fn wait(&self) {
+        thread::yield_now();
+    }
+}
+
+// Waits with the given strategy until ready returns true
+pub fn wait_until<W: WakeStrategy>(strategy: &W, ready: impl Fn() -> bool) {
+    while !ready() {
+        strategy.wait();
+    }
+}
+
+// Every park and unpark in the blocking channels goes through these, so that
+// in the crate's own tests, a test can install a Scheduler that sees (and
+// controls) every one of them, to force a particular interleaving of sender
+// and receiver and reproduce it every time.
+// Outside of tests they're just thread::park and Thread::unpark.
+#[inline]
+pub(crate) fn park() {
+    #[cfg(test)]
+    if let Some(scheduler) = installed() {
+        return scheduler.park();
+    }
+    thread::park();
+}
+
+#[inline]
+pub(crate) fn unpark(thread: &Thread) {
+    #[cfg(test)]
+    if let Some(scheduler) = installed() {
+        return scheduler.unpark(thread);
+    }
+    thread.unpark();
+}
+
+// Takes over parking and unparking while installed. park may return
+// spuriously, as thread::park may, the channels check their condition again
+// either way. An unpark must eventually let the parked thread (or its next
+// park) return, or the thread waits forever.
+#[cfg(test)]
+pub(crate) trait Scheduler: Send + Sync {
+    fn park(&self);
+    fn unpark(&self, thread: &Thread);
+}
+
+// Per thread, so tests running in parallel don't end up in each other's
+// scheduler. A test installs it on every thread taking part, the one
+// unparking as well as the one parking.
+#[cfg(test)]
+thread_local! {
+    static SCHEDULER: RefCell<Option<Arc<dyn Scheduler>>> = const { RefCell::new(None) };
+}
+
+#[cfg(test)]
+fn installed() -> Option<Arc<dyn Scheduler>> {
+    SCHEDULER.with(|s| s.borrow().clone())
+}
+
+// Uninstalls the scheduler on drop. Not Send, as it has to be dropped on the
+// thread it was installed on.
+#[cfg(test)]
+pub(crate) struct SchedulerGuard {
+    _not_send: PhantomData<*const ()>,
+}
+
+// Panics if a scheduler is already installed on this thread
+#[cfg(test)]
+pub(crate) fn install_scheduler(scheduler: Arc<dyn Scheduler>) -> SchedulerGuard {
+    SCHEDULER.with(|s| {
+        let mut installed = s.borrow_mut();
+        assert!(installed.is_none(), "A scheduler is already installed!");
+        *installed = Some(scheduler);
+    });
+    SchedulerGuard { _not_send: PhantomData }
+}
+
+#[cfg(test)]
+impl Drop for SchedulerGuard {
+    fn drop(&mut self) {
+        SCHEDULER.with(|s| *s.borrow_mut() = None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channels::safer_oneshot;
+    use std::sync::{Condvar, Mutex};
+
+    // Parks on a condvar instead of for real, and logs what happens, so the
+    // test can see exactly where the receiver is and decide when it goes on
+    #[derive(Default)]
+    struct Stepper {
+        state: Mutex<Steps>,
+        changed: Condvar,
+    }
+
+    #[derive(Default)]
+    struct Steps {
+        log: Vec<&'static str>,
+        // Like the token of a real unpark
+        token: bool,
+        // Keeps the parking thread at the start of park until released
+        hold: bool,
+    }
+
+    impl Scheduler for Stepper {
+        fn park(&self) {
+            let mut steps = self.state.lock().unwrap();
+            steps.log.push("park");
+            self.changed.notify_all();
+            steps = self.changed.wait_while(steps, |s| s.hold).unwrap();
+            if !steps.token {
+                steps.log.push("blocked");
+                self.changed.notify_all();
+                steps = self.changed.wait_while(steps, |s| !s.token).unwrap();
+            }
+            steps.token = false;
+            steps.log.push("woken");
+        }
+
+        fn unpark(&self, _thread: &Thread) {
+            let mut steps = self.state.lock().unwrap();
+            steps.log.push("unpark");
+            steps.token = true;
+            self.changed.notify_all();
+        }
+    }
+
+    impl Stepper {
+        fn wait_for(&self, step: &str) {
+            let steps = self.state.lock().unwrap();
+            drop(self.changed.wait_while(steps, |s| s.log.last() != Some(&step)).unwrap());
+        }
+    }
+
+    // Runs receive_blocking against a send made once the receiver got as far
+    // as park, either before it blocks (hold) or once it has
+    fn run(hold: bool) -> Vec<&'static str> {
+        let stepper = Arc::new(Stepper::default());
+        stepper.state.lock().unwrap().hold = hold;
+        let c = safer_oneshot::Channel::new();
+        thread::scope(|s| {
+            let receiver = s.spawn(|| {
+                let _guard = install_scheduler(stepper.clone());
+                c.receive_blocking()
+            });
+            let _guard = install_scheduler(stepper.clone());
+            stepper.wait_for(if hold { "park" } else { "blocked" });
+            c.send(7);
+            stepper.state.lock().unwrap().hold = false;
+            stepper.changed.notify_all();
+            assert_eq!(receiver.join().unwrap(), 7);
+        });
+        Arc::try_unwrap(stepper).ok().unwrap().state.into_inner().unwrap().log
+    }
+
+    #[test]
+    fn send_between_registering_and_blocking_isnt_lost() {
+        for _ in 0..20 {
+            assert_eq!(run(true), ["park", "unpark", "woken"]);
+        }
+    }
+
+    #[test]
+    fn send_after_blocking_wakes_the_receiver() {
+        for _ in 0..20 {
+            assert_eq!(run(false), ["park", "blocked", "unpark", "woken"]);
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering::Relaxed;
+use std::thread::{self, Thread};
+use crate::channels::safer_oneshot::{self, RecvError};
+use crate::channels::wake;
+
+// One place for any number of oneshots to keep their waiting receivers, so
+// each channel only needs an id instead of its own waiter slot, for systems
+// with lots of short lived channels, like one response channel per request.
+// Waiters are kept by channel id, so a send only wakes the receiver of that
+// one channel.
+pub struct WakeupRegistry {
+    waiters: Mutex<HashMap<usize, Thread>>,
+    next_id: AtomicUsize,
+}
+
+// A safer_oneshot that parks its receiver through a WakeupRegistry
+pub struct Channel<'r, T> {
+    inner: safer_oneshot::Channel<T>,
+    registry: &'r WakeupRegistry,
+    id: usize,
+}
+
+impl Default for WakeupRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WakeupRegistry {
+    pub fn new() -> Self {
+        Self {
+            waiters: Mutex::new(HashMap::new()),
+            next_id: AtomicUsize::new(0),
+        }
+    }
+
+    // Every id is only used by one channel, nothing else depends on it
+    fn next_id(&self) -> usize {
+        self.next_id.fetch_add(1, Relaxed)
+    }
+
+    // The lock orders this with wake: either wake runs after this and finds
+    // us, or it ran before, in which case everything the sender did before
+    // calling wake (setting ready) is visible to us once we've unlocked
+    fn register(&self, id: usize) {
+        self.waiters.lock().unwrap().insert(id, thread::current());
+    }
+
+    fn unregister(&self, id: usize) {
+        self.waiters.lock().unwrap().remove(&id);
+    }
+
+    fn wake(&self, id: usize) {
+        let waiter = self.waiters.lock().unwrap().remove(&id);
+        if let Some(thread) = waiter {
+            wake::unpark(&thread);
+        }
+    }
+
+    // How many receivers are parked right now
+    pub fn waiting(&self) -> usize {
+        self.waiters.lock().unwrap().len()
+    }
+}
+
+impl<'r, T> Channel<'r, T> {
+    pub fn new(registry: &'r WakeupRegistry) -> Self {
+        Self { inner: safer_oneshot::Channel::new(), registry, id: registry.next_id() }
+    }
+
+    /// Panics like safer_oneshot::Channel::send
+    pub fn send(&self, message: T) {
+        self.inner.send(message);
+        self.registry.wake(self.id);
+    }
+
+    pub fn close(&self) {
+        self.inner.close();
+        self.registry.wake(self.id);
+    }
+
+    pub fn try_receive(&self) -> Result<T, RecvError> {
+        self.inner.try_receive()
+    }
+
+    /// Parks until the message arrives, or the channel is closed.
+    /// Panics if the message was already consumed
+    pub fn receive(&self) -> Result<T, RecvError> {
+        loop {
+            self.registry.register(self.id);
+            match self.inner.try_receive() {
+                Err(RecvError::Empty) => wake::park(),
+                result => {
+                    // We didn't need waking up after all
+                    self.registry.unregister(self.id);
+                    return result;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn send_wakes_only_its_own_receiver() {
+        let registry = WakeupRegistry::new();
+        let channels: Vec<_> = (0..100).map(|_| Channel::new(&registry)).collect();
+        thread::scope(|s| {
+            let mut receivers: Vec<_> = channels.iter().map(|c| s.spawn(|| c.receive())).collect();
+            while registry.waiting() < 100 {
+                thread::yield_now();
+            }
+            channels[42].send(42);
+            assert_eq!(receivers.remove(42).join().unwrap(), Ok(42));
+            // Everyone else is still parked (or went right back to it)
+            assert_eq!(registry.waiting(), 99);
+            for (i, c) in channels.iter().enumerate().rev() {
+                if i != 42 {
+                    c.send(i);
+                }
+            }
+            for (h, i) in receivers.into_iter().zip((0..100).filter(|&i| i != 42)) {
+                assert_eq!(h.join().unwrap(), Ok(i));
+            }
+        });
+        assert_eq!(registry.waiting(), 0);
+    }
+
+    #[test]
+    fn close_wakes_the_receiver() {
+        let registry = WakeupRegistry::new();
+        let c = Channel::<()>::new(&registry);
+        thread::scope(|s| {
+            let h = s.spawn(|| c.receive());
+            while registry.waiting() == 0 {
+                thread::yield_now();
+            }
+            c.close();
+            assert_eq!(h.join().unwrap(), Err(RecvError::Closed));
+        });
+    }
+}
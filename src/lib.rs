@@ -0,0 +1,14 @@
+// The channels and locks, as a library, so other crates (and tests/) can use
+// them. main.rs is just a demo on top of it.
+// Without the std feature (on by default), only the oneshots that can do
+// without std are built, see channels/mod.rs. The tests always have std.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+pub mod channels;
+#[cfg(feature = "std")]
+pub mod locks;
+
+// The channels most of the rest builds on
+#[cfg(feature = "std")]
+pub use channels::{compile_time_oneshot, mutex_based};
+pub use channels::safer_oneshot;
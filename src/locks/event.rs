@@ -0,0 +1,43 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+// A one-time signal without any data: once set, it stays set, and every
+// thread waiting on it wakes up, as does every wait after that.
+// Clones share the same event, so it can be handed to a callback that has to
+// be 'static (see safer_oneshot::Channel::set_event_on_ready).
+// Built on a Mutex and Condvar, like Semaphore.
+#[derive(Clone, Default)]
+pub struct Event {
+    shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Shared {
+    set: Mutex<bool>,
+    was_set: Condvar,
+}
+
+impl Event {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Wakes up every waiting thread. Setting it again does nothing.
+    pub fn set(&self) {
+        *self.shared.set.lock().unwrap() = true;
+        self.shared.was_set.notify_all();
+    }
+
+    // Going through the mutex, seeing true means everything done before set
+    // is visible to us
+    pub fn is_set(&self) -> bool {
+        *self.shared.set.lock().unwrap()
+    }
+
+    // Blocks until set has been called, returns right away if it already was
+    pub fn wait(&self) {
+        let mut set = self.shared.set.lock().unwrap();
+        while !*set {
+            set = self.shared.was_set.wait(set).unwrap();
+        }
+    }
+}
@@ -0,0 +1,3 @@
+pub mod spin_lock;
+pub mod semaphore;
+pub mod event;
\ No newline at end of file
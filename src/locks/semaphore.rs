@@ -0,0 +1,57 @@
+use std::sync::{Condvar, Mutex};
+
+// Counting semaphore: hands out up to a fixed number of permits at a time,
+// acquire blocks while they're all taken.
+// Built on a Mutex and Condvar, like mutex_based::Channel, as waiting threads
+// need to sleep rather than spin.
+pub struct Semaphore {
+    permits: Mutex<usize>,
+    permit_released: Condvar,
+}
+
+// Gives the permit back on drop
+pub struct SemaphorePermit<'a> {
+    semaphore: &'a Semaphore,
+}
+
+impl Semaphore {
+    pub const fn new(permits: usize) -> Self {
+        Self {
+            permits: Mutex::new(permits),
+            permit_released: Condvar::new(),
+        }
+    }
+
+    // Blocks until a permit is available
+    pub fn acquire(&self) -> SemaphorePermit<'_> {
+        let mut permits = self.permits.lock().unwrap();
+        while *permits == 0 {
+            permits = self.permit_released.wait(permits).unwrap();
+        }
+        *permits -= 1;
+        SemaphorePermit { semaphore: self }
+    }
+
+    // Returns None instead of blocking when there are no permits left
+    pub fn try_acquire(&self) -> Option<SemaphorePermit<'_>> {
+        let mut permits = self.permits.lock().unwrap();
+        if *permits == 0 {
+            return None;
+        }
+        *permits -= 1;
+        Some(SemaphorePermit { semaphore: self })
+    }
+
+    // How many permits are free right now, may be stale by the time the
+    // caller looks at it
+    pub fn available(&self) -> usize {
+        *self.permits.lock().unwrap()
+    }
+}
+
+impl Drop for SemaphorePermit<'_> {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.permit_released.notify_one();
+    }
+}
@@ -0,0 +1,319 @@
+use std::cell::UnsafeCell;
+use std::ops::{Deref, DerefMut};
+use std::sync::{LockResult, PoisonError};
+use std::sync::atomic::{AtomicBool, AtomicUsize};
+use std::sync::atomic::Ordering::{Acquire, Relaxed, Release};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// A spin lock: lock keeps trying until it gets the lock, instead of putting
+// the thread to sleep like std's Mutex does.
+// Pros: Very cheap to lock and unlock when there's no contention, and when the
+// lock is only held for a very short time
+// Cons: A waiting thread burns CPU time the whole time, which is terrible if
+// the lock is held for long (or the holder gets descheduled). with_max_spin
+// puts a limit on that.
+pub struct SpinLock<T> {
+    locked: AtomicBool,
+    // How long lock spins before it starts yielding instead, see with_max_spin
+    max_spin: Option<Duration>,
+    // Only for reporting, so Relaxed is enough
+    yielded: AtomicUsize,
+    value: UnsafeCell<T>,
+}
+
+// Reading the clock costs a lot more than a spin, so lock only looks at it
+// once every this many spins
+const SPINS_PER_CLOCK_CHECK: u32 = 64;
+
+// Like Mutex, the lock gives out exclusive access to the value to one thread
+// at a time, so T only has to be Send for the lock to be Sync
+unsafe impl<T> Sync for SpinLock<T> where T: Send {}
+
+// The guard's lifetime proves the lock is held, so it's safe to give out
+// references to the value through it. Unlocks on drop.
+pub struct SpinLockGuard<'a, T> {
+    lock: &'a SpinLock<T>,
+}
+
+// Sharing the guard shares a &T, so it may only be Sync when T is. The
+// automatic impl would follow &SpinLock<T> instead, which is Sync as soon as
+// T is Send, letting threads share a &Cell through a shared guard.
+/// ```compile_fail
+/// use std::cell::Cell;
+/// use rust_atomics_and_locks::locks::spin_lock::SpinLock;
+/// let lock = SpinLock::new(Cell::new(0));
+/// let guard = lock.lock();
+/// std::thread::scope(|s| {
+///     s.spawn(|| guard.set(1));
+/// });
+/// ```
+unsafe impl<T> Sync for SpinLockGuard<'_, T> where T: Sync {}
+
+impl<T> SpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            max_spin: None,
+            yielded: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    // A lock that only spins for about max_spin, then falls back to yielding
+    // to other threads in between tries, so a waiter doesn't burn a core for
+    // as long as a descheduled holder is gone. Spinning starts being timed
+    // after the first SPINS_PER_CLOCK_CHECK spins, so a short wait never
+    // reads the clock at all.
+    pub const fn with_max_spin(value: T, max_spin: Duration) -> Self {
+        Self {
+            locked: AtomicBool::new(false),
+            max_spin: Some(max_spin),
+            yielded: AtomicUsize::new(0),
+            value: UnsafeCell::new(value),
+        }
+    }
+
+    // Acquire pairs with the Release in the guard's drop, so everything the
+    // previous holder did to the value is visible to us
+    pub fn lock(&self) -> SpinLockGuard<'_, T> {
+        let mut spins = 0u32;
+        let mut started = None;
+        while self.locked.swap(true, Acquire) {
+            spins = spins.wrapping_add(1);
+            if let Some(max_spin) = self.max_spin {
+                if spins.is_multiple_of(SPINS_PER_CLOCK_CHECK) {
+                    let started = *started.get_or_insert_with(Instant::now);
+                    if started.elapsed() >= max_spin {
+                        self.yielded.fetch_add(1, Relaxed);
+                        while self.locked.swap(true, Acquire) {
+                            thread::yield_now();
+                        }
+                        break;
+                    }
+                }
+            }
+            std::hint::spin_loop();
+        }
+        SpinLockGuard { lock: self }
+    }
+
+    // How many lock calls spun for longer than max_spin, and went on to
+    // yielding. Always 0 without a max_spin.
+    pub fn yield_fallbacks(&self) -> usize {
+        self.yielded.load(Relaxed)
+    }
+}
+
+impl<'a, T> SpinLockGuard<'a, T> {
+    // Unlocks, runs f, and locks again before returning, so f can block
+    // without holding up everyone else waiting for the lock.
+    // An associated function rather than a method, so it doesn't get mixed up
+    // with methods of T through Deref.
+    pub fn unlocked<R>(guard: Self, f: impl FnOnce() -> R) -> (R, Self) {
+        let lock = guard.lock;
+        drop(guard);
+        let result = f();
+        (result, lock.lock())
+    }
+}
+
+impl<T> Deref for SpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        // Safety: The existence of this guard guarantees we've exclusively
+        // locked the lock
+        unsafe { &*self.lock.value.get() }
+    }
+}
+
+impl<T> DerefMut for SpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        // Safety: Same as above
+        unsafe { &mut *self.lock.value.get() }
+    }
+}
+
+impl<T> Drop for SpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        self.lock.locked.store(false, Release);
+    }
+}
+
+// A SpinLock that gets poisoned like std's Mutex: if a thread panics while
+// holding the lock, the value might have been left half updated, so every
+// lock after that gives a PoisonError. The guard is still inside the error,
+// so the value can be looked at (and fixed) with into_inner.
+// Its own type rather than a flag on SpinLock, so the plain SpinLock doesn't
+// pay for the check, and its lock keeps returning the guard directly.
+pub struct PoisoningSpinLock<T> {
+    lock: SpinLock<T>,
+    // Only set and read while holding the lock, so Relaxed is enough
+    poisoned: AtomicBool,
+}
+
+pub struct PoisoningSpinLockGuard<'a, T> {
+    guard: SpinLockGuard<'a, T>,
+    poisoned: &'a AtomicBool,
+    // Whether we were already panicking when we locked, in which case
+    // panicking when unlocking isn't a new panic under the lock (a Drop
+    // that locks while unwinding, say)
+    panicking: bool,
+}
+
+// Same as SpinLockGuard. The field already makes it so, this just says it
+unsafe impl<T> Sync for PoisoningSpinLockGuard<'_, T> where T: Sync {}
+
+impl<T> PoisoningSpinLock<T> {
+    pub const fn new(value: T) -> Self {
+        Self { lock: SpinLock::new(value), poisoned: AtomicBool::new(false) }
+    }
+
+    pub fn lock(&self) -> LockResult<PoisoningSpinLockGuard<'_, T>> {
+        let guard = PoisoningSpinLockGuard {
+            guard: self.lock.lock(),
+            poisoned: &self.poisoned,
+            panicking: thread::panicking(),
+        };
+        if self.poisoned.load(Relaxed) {
+            Err(PoisonError::new(guard))
+        } else {
+            Ok(guard)
+        }
+    }
+
+    // Can be out of date right away, unless we're holding the lock
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned.load(Relaxed)
+    }
+
+    // For once the value has been fixed, after which lock succeeds again
+    pub fn clear_poison(&self) {
+        let _guard = self.lock.lock();
+        self.poisoned.store(false, Relaxed);
+    }
+}
+
+impl<T> Deref for PoisoningSpinLockGuard<'_, T> {
+    type Target = T;
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for PoisoningSpinLockGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+// Runs before the inner guard's drop unlocks, so the flag is set while we
+// still hold the lock, and the Release when unlocking publishes it
+impl<T> Drop for PoisoningSpinLockGuard<'_, T> {
+    fn drop(&mut self) {
+        if !self.panicking && thread::panicking() {
+            self.poisoned.store(true, Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic::{self, AssertUnwindSafe};
+
+    #[test]
+    fn panic_under_the_lock_poisons_it() {
+        let lock = PoisoningSpinLock::new(vec![1]);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut v = lock.lock().unwrap();
+            v.push(2);
+            panic!("halfway through");
+        }));
+        assert!(result.is_err());
+        assert!(lock.is_poisoned());
+        // The value is still there to look at, and fix
+        let Err(poisoned) = lock.lock() else {
+            panic!("Should be poisoned!");
+        };
+        let mut v = poisoned.into_inner();
+        assert_eq!(*v, [1, 2]);
+        v.pop();
+        drop(v);
+        lock.clear_poison();
+        assert_eq!(*lock.lock().unwrap(), [1]);
+    }
+
+    #[test]
+    fn panic_on_another_thread_poisons_it() {
+        let lock = PoisoningSpinLock::new(0);
+        thread::scope(|s| {
+            let h = s.spawn(|| {
+                let _guard = lock.lock().unwrap();
+                panic!("under the lock");
+            });
+            assert!(h.join().is_err());
+        });
+        assert!(lock.lock().is_err());
+    }
+
+    #[test]
+    fn locking_while_already_unwinding_doesnt_poison() {
+        struct LocksOnDrop<'a>(&'a PoisoningSpinLock<i32>);
+        impl Drop for LocksOnDrop<'_> {
+            fn drop(&mut self) {
+                *self.0.lock().unwrap() += 1;
+            }
+        }
+        let lock = PoisoningSpinLock::new(0);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _l = LocksOnDrop(&lock);
+            panic!("not under the lock");
+        }));
+        assert!(result.is_err());
+        assert!(!lock.is_poisoned());
+        assert_eq!(*lock.lock().unwrap(), 1);
+    }
+
+    // Holds the lock for much longer than max_spin while another thread
+    // waits for it
+    fn wait_behind_a_long_hold(lock: &SpinLock<i32>) {
+        let waiting = AtomicBool::new(false);
+        let guard = lock.lock();
+        thread::scope(|s| {
+            s.spawn(|| {
+                waiting.store(true, Relaxed);
+                *lock.lock() += 1;
+            });
+            while !waiting.load(Relaxed) {
+                thread::yield_now();
+            }
+            thread::sleep(Duration::from_millis(50));
+            drop(guard);
+        });
+        assert_eq!(*lock.lock(), 1);
+    }
+
+    #[test]
+    fn with_max_spin_falls_back_to_yielding() {
+        let lock = SpinLock::with_max_spin(0, Duration::from_millis(1));
+        wait_behind_a_long_hold(&lock);
+        assert_eq!(lock.yield_fallbacks(), 1);
+    }
+
+    #[test]
+    fn without_max_spin_it_only_spins() {
+        let lock = SpinLock::new(0);
+        wait_behind_a_long_hold(&lock);
+        assert_eq!(lock.yield_fallbacks(), 0);
+    }
+
+    #[test]
+    fn uncontended_lock_doesnt_yield() {
+        let lock = SpinLock::with_max_spin(0, Duration::ZERO);
+        for _ in 0..10 {
+            *lock.lock() += 1;
+        }
+        assert_eq!(lock.yield_fallbacks(), 0);
+    }
+}
@@ -1,23 +1,24 @@
-mod channels;
-
+#[cfg(feature = "std")]
 use std::thread;
-use channels::safer_oneshot;
-use channels::compile_time_oneshot;
+#[cfg(feature = "std")]
+use rust_atomics_and_locks::{compile_time_oneshot, safer_oneshot};
+#[cfg(feature = "std")]
+use rust_atomics_and_locks::channels::wake::ParkStrategy;
+
+// The demo needs the parts of the library that need std, there's nothing to
+// show without the std feature
+#[cfg(not(feature = "std"))]
+fn main() {}
 
+#[cfg(feature = "std")]
 fn main() {
     let channel = safer_oneshot::Channel::new();
-    let t = thread::current();
     thread::scope(|s| {
         s.spawn(|| {
             channel.send("Hello World!");
-            t.unpark();
         });
 
-        while !channel.is_ready() {
-            thread::park();
-        }
-
-        assert_eq!(channel.receive(), "Hello World!");
+        assert_eq!(channel.receive_blocking(), "Hello World!");
     });
 
     thread::scope(|s| {
@@ -25,13 +26,8 @@ fn main() {
 
         s.spawn(|| {
             sender.send("Hello World!");
-            t.unpark()
         });
 
-        while !receiver.is_ready() {
-            thread::park();
-        }
-
-        assert_eq!(receiver.receive(), "Hello World!");
+        assert_eq!(receiver.receive_with(ParkStrategy), Ok("Hello World!"));
     });
 }
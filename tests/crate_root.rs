@@ -0,0 +1,41 @@
+// Needs the std parts of the library
+#![cfg(feature = "std")]
+
+use rust_atomics_and_locks::channels::spsc_ring;
+use rust_atomics_and_locks::locks::spin_lock::SpinLock;
+use rust_atomics_and_locks::{compile_time_oneshot, mutex_based, safer_oneshot};
+use std::thread;
+
+// Uses the channels from outside the crate, through the re-exports at the
+// crate root and through channels::, to catch anything that's meant to be
+// public and isn't.
+#[test]
+fn channels_are_usable_from_outside_the_crate() {
+    let oneshot = safer_oneshot::Channel::new();
+    let (s, r) = compile_time_oneshot::channel();
+    let queue = mutex_based::Channel::new();
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            oneshot.send(1);
+            s.send(2);
+            queue.send(3);
+        });
+    });
+    assert_eq!(oneshot.receive(), 1);
+    assert_eq!(r.receive(), Ok(2));
+    assert_eq!(queue.receive(), 3);
+}
+
+#[test]
+fn locks_are_usable_from_outside_the_crate() {
+    let lock = SpinLock::new(0);
+    thread::scope(|scope| {
+        for _ in 0..4 {
+            scope.spawn(|| *lock.lock() += 1);
+        }
+    });
+    assert_eq!(*lock.lock(), 4);
+    let (tx, rx) = spsc_ring::channel(4);
+    assert!(tx.try_send(5).is_ok());
+    assert_eq!(rx.try_recv(), Some(5));
+}
@@ -0,0 +1,18 @@
+use std::path::Path;
+use std::process::Command;
+
+// Builds the library without the std feature, to catch anything that starts
+// using std in the modules that are supposed to need only core. The crate
+// is no_std then, so any use of std in them doesn't compile.
+// Into its own target dir, the one we're running from is locked by the cargo
+// running this test.
+#[test]
+fn library_builds_without_std() {
+    let status = Command::new(env!("CARGO"))
+        .args(["build", "--lib", "--offline", "--no-default-features", "--target-dir"])
+        .arg(Path::new(env!("CARGO_TARGET_TMPDIR")).join("no_std"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .unwrap();
+    assert!(status.success(), "cargo build --no-default-features failed");
+}